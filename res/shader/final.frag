@@ -14,7 +14,8 @@ uniform vec3 u_light_positions[max_lights];
 uniform vec3 u_light_colors[max_lights];
 uniform int u_n_lights;
 
-uniform vec3 u_eye;
+// w = 1 for the eye's position, w = 0 for the direction towards it under ortho
+uniform vec4 u_eye;
 
 void main() {
   vec3 f_pos = texture(f_pos, v_uv).rgb;
@@ -36,7 +37,7 @@ void main() {
     float diffuse_strength = max(dot(f_norm, light_dir), 0.) * 0.4;
     diffuse += diffuse_strength * light_col;
 
-    vec3 view_dir = normalize(u_eye - f_pos);
+    vec3 view_dir = normalize(u_eye.xyz - f_pos * u_eye.w);
     vec3 halfway_dir = normalize(light_dir + view_dir);
     float specular_strength = pow(max(dot(f_norm, halfway_dir), 0.), f_spec);
     specular += specular_strength * light_col;
@@ -8,7 +8,8 @@ uniform sampler2D f_pos;
 uniform sampler2D f_norm;
 uniform isampler2D f_tint;
 
-uniform vec3 u_eye;
+// w = 1 for the eye's position, w = 0 for the direction towards it under ortho
+uniform vec4 u_eye;
 
 const int n_colors = 64;
 uniform vec3 palette[n_colors * 4];
@@ -32,7 +33,7 @@ void main() {
   const vec3 light_dir = normalize(vec3(0.66, 2, 0.66));
   float diffuse = max(dot(f_norm, light_dir), 0.) * 0.4;
 
-  vec3 view_dir = normalize(u_eye - f_pos);
+  vec3 view_dir = normalize(u_eye.xyz - f_pos * u_eye.w);
   vec3 halfway_dir = normalize(light_dir + view_dir);
   float specular = pow(max(dot(f_norm, halfway_dir), 0.), f_spec);
 
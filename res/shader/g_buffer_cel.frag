@@ -2,15 +2,32 @@
 
 layout (location = 1) in vec3 v_norm;
 layout (location = 2) in vec3 v_pos;
+layout (location = 3) flat in int v_tint;
+layout (location = 4) in vec2 v_tex;
+layout (location = 5) flat in uint v_id;
 
 layout (location = 0) out vec4 f_pos;
 layout (location = 1) out vec4 f_norm;
 layout (location = 2) out ivec2 f_tint;
+// pick::ID_ATTACHMENT
+layout (location = 3) out uint f_id;
 
 uniform int tint;
+uniform usampler2D u_index_tex;
+uniform int u_indexed;
 
 void main() {
   f_pos = vec4(v_pos, 1.);
   f_norm = vec4(normalize(v_norm), 1.);
-  f_tint = ivec2(tint, 64);
+  int res = v_tint < 0 ? tint : v_tint;
+  if (u_indexed != 0) {
+    // 255 marks texels left unpainted
+    uint idx = texture(u_index_tex, v_tex).r;
+    if (idx != 255u) {
+      res = int(idx);
+    }
+  }
+
+  f_tint = ivec2(res, 64);
+  f_id = v_id;
 }
\ No newline at end of file
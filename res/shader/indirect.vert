@@ -0,0 +1,49 @@
+#version 460 core
+
+layout (location = 0) in vec3 i_pos;
+layout (location = 1) in vec3 i_norm;
+layout (location = 2) in int i_tint;
+layout (location = 3) in vec2 i_tex;
+
+layout (location = 1) out vec3 v_norm;
+layout (location = 2) out vec3 v_pos;
+layout (location = 3) flat out int v_tint;
+layout (location = 4) out vec2 v_tex;
+layout (location = 5) flat out uint v_id;
+
+uniform mat4 u_proj;
+uniform mat4 u_look;
+// batch::DrawData, packed meshes are opt::PackedVertex, see packed.glsl
+struct Draw {
+  mat4 model;
+  vec4 quant_min;
+  vec4 quant_size;
+  int tint;
+  int packed;
+  uint id;
+};
+
+// batch::DRAW_BINDING
+layout (std430, binding = 0) readonly buffer Draws {
+  Draw draws[];
+};
+
+#include "packed.glsl"
+
+void main() {
+  Draw draw = draws[gl_BaseInstance + gl_InstanceID];
+  vec3 pos = i_pos;
+  vec3 norm = i_norm;
+  if (draw.packed != 0) {
+    unpack(draw.quant_min.xyz, draw.quant_size.xyz, pos, norm);
+  }
+
+  mat4 model = draw.model;
+  vec4 world = model * vec4(pos, 1.0);
+  gl_Position = u_proj * u_look * world;
+  v_norm = normalize(transpose(inverse(mat3(model))) * norm);
+  v_pos = world.xyz;
+  v_tint = draw.tint >= 0 ? draw.tint : i_tint;
+  v_tex = i_tex;
+  v_id = draw.id;
+}
\ No newline at end of file
@@ -0,0 +1,19 @@
+// model::MAX_MORPHS and model::MORPH_BINDING. deltas are (pos, 0) (norm, 0) per vertex,
+// one target after another, u_base_vertex gets gl_VertexID back to the mesh's own vertices
+const int max_morphs = 32;
+layout (std430, binding = 1) readonly buffer Morphs {
+  vec4 morph_deltas[];
+};
+
+uniform int u_morph_count = 0;
+uniform float u_morph_weights[max_morphs];
+uniform int u_morph_vertices;
+uniform int u_base_vertex;
+
+void morph(inout vec3 pos, inout vec3 norm) {
+  for (int i = 0; i < u_morph_count; i++) {
+    int at = (i * u_morph_vertices + gl_VertexID - u_base_vertex) * 2;
+    pos += morph_deltas[at].xyz * u_morph_weights[i];
+    norm += morph_deltas[at + 1].xyz * u_morph_weights[i];
+  }
+}
\ No newline at end of file
@@ -0,0 +1,14 @@
+// opt::PackedVertex, positions are 0..1 across the mesh bounds and normals are octahedral
+vec3 oct_decode(vec2 e) {
+  vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+  if (n.z < 0.0) {
+    n.xy = (1.0 - abs(n.yx)) * vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+  }
+
+  return normalize(n);
+}
+
+void unpack(vec3 quant_min, vec3 quant_size, inout vec3 pos, inout vec3 norm) {
+  pos = quant_min + pos * quant_size;
+  norm = oct_decode(norm.xy);
+}
\ No newline at end of file
@@ -0,0 +1,61 @@
+#version 460 core
+
+layout (location = 0) in vec3 i_pos;
+layout (location = 1) in vec3 i_norm;
+layout (location = 2) in int i_tint;
+layout (location = 3) in vec2 i_tex;
+layout (location = 4) in ivec4 i_bones;
+layout (location = 5) in vec4 i_weights;
+
+layout (location = 1) out vec3 v_norm;
+layout (location = 2) out vec3 v_pos;
+layout (location = 3) flat out int v_tint;
+layout (location = 4) out vec2 v_tex;
+layout (location = 5) flat out uint v_id;
+
+const int max_bones = 128;
+
+uniform mat4 u_proj;
+uniform mat4 u_look;
+uniform mat4 u_model;
+uniform mat3 u_norm;
+// per object, replaces the vertex tints when >= 0
+uniform int u_tint_override = -1;
+// World::by_id, 0 for things that can't be picked
+uniform uint u_id = 0u;
+// set when the mesh is opt::PackedVertex, see packed.glsl
+uniform int u_packed;
+uniform vec3 u_quant_min;
+uniform vec3 u_quant_size;
+uniform mat4 u_bones[max_bones];
+uniform int u_skinned;
+
+#include "morph.glsl"
+#include "packed.glsl"
+
+void main() {
+  vec3 pos = i_pos;
+  vec3 norm = i_norm;
+  if (u_packed != 0) {
+    unpack(u_quant_min, u_quant_size, pos, norm);
+  }
+
+  morph(pos, norm);
+
+  mat4 skin = mat4(1.);
+  if (u_skinned != 0) {
+    skin =
+      u_bones[i_bones.x] * i_weights.x +
+      u_bones[i_bones.y] * i_weights.y +
+      u_bones[i_bones.z] * i_weights.z +
+      u_bones[i_bones.w] * i_weights.w;
+  }
+
+  vec4 world = u_model * skin * vec4(pos, 1.0);
+  gl_Position = u_proj * u_look * world;
+  v_norm = normalize(u_norm * mat3(skin) * norm);
+  v_pos = world.xyz;
+  v_tint = u_tint_override >= 0 ? u_tint_override : i_tint;
+  v_tex = i_tex;
+  v_id = u_id;
+}
\ No newline at end of file
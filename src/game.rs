@@ -0,0 +1,692 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::f32::consts::{FRAC_PI_2, TAU};
+use glam::{IVec2, Mat4, Quat, Vec2, Vec3};
+use glfw::{CursorMode, WindowEvent};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::hana::anim::{Animator, Channel, Clip, TICK_LENGTH};
+use crate::hana::app::{Ctx, State, Trans};
+use crate::hana::builder::MeshBuilder;
+use crate::hana::camera::{Camera, DIMETRIC, ISOMETRIC, Mode, Orbit, Projection};
+use crate::hana::cine::{CamPath, Ease, Spline};
+use crate::hana::entity::Object;
+use crate::hana::glu::*;
+use crate::hana::model::{ImportOptions, Model, Morph, Node, Normals};
+use crate::hana::palette::{hex_to_vec3, Palette};
+use crate::hana::pick::{ID_ATTACHMENT, NO_ID, Picker};
+use crate::hana::ray::Ray;
+use crate::hana::replay::{checksum, Recording, Replay};
+use crate::hana::transform::Transform;
+use crate::hana::world::World;
+use crate::BINDINGS_PATH;
+
+// palette ramps
+const BLUE: i32 = 0;
+const GREEN: i32 = 1;
+const YELLOW: i32 = 2;
+const ORANGE: i32 = 3;
+const PINK: i32 = 4;
+const WHITE: i32 = 5;
+
+// a row of generated props in front of where the player starts, sitting on y = 0
+fn props() -> MeshBuilder {
+  let mut torch = MeshBuilder::new();
+  torch
+    .merge_transformed(MeshBuilder::new().tint(YELLOW).cylinder(0.08, 1.6, 8), &Mat4::from_translation(Vec3::Y * 0.8))
+    .merge_transformed(MeshBuilder::new().tint(ORANGE).sphere(0.2, 8, 6), &Mat4::from_translation(Vec3::Y * 1.7));
+
+  let vase = [(0., 0.), (0.3, 0.), (0.3, 0.), (0.4, 0.3), (0.2, 0.7), (0.25, 0.9)].map(|(x, y)| Vec2::new(x, y));
+  let star = (0..10).map(|i| Vec2::from_angle(i as f32 * TAU / 10.) * if i % 2 == 0 { 0.5 } else { 0.2 }).collect::<Vec<_>>();
+
+  let mut res = MeshBuilder::new();
+  res
+    .merge(&torch)
+    .merge_transformed(MeshBuilder::new().tint(BLUE).lathe(&vase, 12), &Mat4::from_translation(Vec3::X * -1.5))
+    .merge_transformed(MeshBuilder::new().tint(PINK).torus(0.5, 0.12, 16, 8), &Mat4::from_rotation_translation(Quat::from_rotation_x(FRAC_PI_2), Vec3::new(1.5, 0.62, 0.)))
+    .merge_transformed(MeshBuilder::new().tint(BLUE).capsule(0.25, 0.6, 10, 8), &Mat4::from_translation(Vec3::new(3., 0.55, 0.)))
+    .merge_transformed(MeshBuilder::new().tint(YELLOW).extrude(&star, 0.15), &Mat4::from_translation(Vec3::new(-3., 0.5, 0.)))
+    .merge_transformed(MeshBuilder::new().tint(ORANGE).cube(Vec3::splat(0.6)), &Mat4::from_translation(Vec3::new(-4.5, 0.3, 0.)));
+  res
+}
+
+// a post with blades on a hub node, clips spin the hub or idle it, sway leans the post and is
+// meant to be blended on top. the furl morph pulls the blades in to the hub
+fn windmill() -> Model {
+  let post = MeshBuilder::new().tint(WHITE).cylinder(0.15, 4., 8).build();
+  let mut blades = MeshBuilder::new().tint(ORANGE).cube(Vec3::new(0.3, 3.2, 0.05)).cube(Vec3::new(3.2, 0.3, 0.05)).build();
+  let furl = Morph {
+    name: "furl".into(),
+    pos: blades.vertices.iter().map(|it| it.pos * Vec3::new(-0.75, -0.75, 0.)).collect(),
+    norm: vec![Vec3::ZERO; blades.vertices.len()],
+    slot: 0
+  };
+
+  blades.set_morphs(vec![furl]);
+  let nodes = vec![
+    Node { name: "post".into(), transform: Mat4::from_translation(Vec3::Y * 2.), parent: None, meshes: vec![0] },
+    Node { name: "hub".into(), transform: Mat4::from_translation(Vec3::new(0., 1.8, 0.2)), parent: Some(0), meshes: vec![1] }
+  ];
+
+  let hub = |rot: Vec<(f32, Quat)>| Channel { node: 1, pos: Vec::new(), rot, scale: Vec::new() };
+  let clips = vec![
+    Clip { name: "idle".into(), duration: 1., channels: vec![hub(vec![(0., Quat::IDENTITY)])] },
+    // a quarter turn a second, keys any further apart would slerp the short way round
+    Clip { name: "spin".into(), duration: 4., channels: vec![hub((0..=4).map(|i| (i as f32, Quat::from_rotation_z(i as f32 * -FRAC_PI_2))).collect())] },
+    Clip {
+      name: "sway".into(),
+      duration: 3.,
+      channels: vec![Channel {
+        node: 0,
+        pos: Vec::new(),
+        rot: vec![(0., Quat::IDENTITY), (1.5, Quat::from_rotation_x(0.1)), (3., Quat::IDENTITY)],
+        scale: Vec::new()
+      }]
+    }
+  ];
+
+  Model::assemble(vec![post, blades], Vec::new(), nodes, Vec::new(), clips)
+}
+
+// the windmill spins up while the camera is closer than this
+const WINDMILL_RANGE: f32 = 12.;
+
+// what the g buffer pass draws into, in g_buffer_cel.frag's output order
+const G_DRAW_BUFFERS: [u32; 4] = [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1, gl::COLOR_ATTACHMENT2, ID_ATTACHMENT];
+
+// where the save_path and load_path actions put the camera path
+const SHOT_PATH: &str = "camera.path";
+
+// what main's flags asked for
+#[derive(Default)]
+pub struct Options {
+  // a camera path to render to numbered pngs in a directory at some fps, then stop
+  pub render: Option<(CamPath, String, f32)>,
+  // where to save the session's input on exit
+  pub record: Option<String>,
+  pub replay: Option<Replay>,
+  // run the replay through without drawing anything, failing if the simulation doesn't match
+  pub verify: bool
+}
+
+// waits for the start action before loading anything
+pub struct Menu {
+  options: Option<Options>
+}
+
+impl Menu {
+  pub fn new(options: Options) -> Menu {
+    Menu { options: Some(options) }
+  }
+}
+
+impl State for Menu {
+  fn input(&mut self, ctx: &mut Ctx) -> Result<Trans, String> {
+    if !ctx.input.take("start") {
+      return Ok(Trans::None);
+    }
+
+    let options = self.options.take().unwrap_or_default();
+    Ok(Trans::Switch(Box::new(Gameplay::new(ctx, options)?)))
+  }
+
+  fn render(&mut self, ctx: &mut Ctx, _tick_delta: f32) -> Result<Trans, String> {
+    gl_viewport(ctx.width, ctx.height);
+    ctx.win.fbo0().bind();
+    gl_clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    ctx.win.set_title("hana | press enter to start");
+    Ok(Trans::None)
+  }
+}
+
+// stops the clock over the game, which keeps drawing under it and can still be stepped through
+pub struct Pause;
+
+impl State for Pause {
+  fn init(&mut self, ctx: &mut Ctx) -> Result<(), String> {
+    ctx.game.paused = true;
+    Ok(())
+  }
+
+  fn input(&mut self, ctx: &mut Ctx) -> Result<Trans, String> {
+    if ctx.input.take("step") {
+      ctx.game.step();
+    }
+
+    if ctx.input.take("rebind") {
+      return Ok(Trans::Push(Box::new(Rebind::new())));
+    }
+
+    Ok(if ctx.input.take("pause") { Trans::Pop } else { Trans::None })
+  }
+
+  fn exit(&mut self, ctx: &mut Ctx) -> Result<(), String> {
+    ctx.game.paused = false;
+    Ok(())
+  }
+
+  fn overlay(&self) -> bool {
+    true
+  }
+}
+
+// over the pause, waits for a bound button to pick which action to rebind, then for the button
+// that replaces everything bound to it, and saves the bindings to where main reads them from
+pub struct Rebind {
+  action: Option<String>,
+  // shown in the title
+  prompt: String
+}
+
+impl Rebind {
+  pub fn new() -> Rebind {
+    Rebind { action: None, prompt: "press a button of the action to rebind".into() }
+  }
+}
+
+impl State for Rebind {
+  fn init(&mut self, ctx: &mut Ctx) -> Result<(), String> {
+    // the rebind press itself
+    ctx.input.take_last();
+    Ok(())
+  }
+
+  fn input(&mut self, ctx: &mut Ctx) -> Result<Trans, String> {
+    let Some(button) = ctx.input.take_last() else {
+      return Ok(Trans::None);
+    };
+
+    let Some(action) = self.action.take() else {
+      let bound = ctx.input.bindings.actions.iter().find(|(_, it)| it.contains(&button)).map(|it| it.0.clone());
+      self.prompt = match &bound {
+        Some(action) => format!("press the new button for {}", action),
+        None => format!("{} isn't bound to anything, press a button of the action to rebind", button.name())
+      };
+
+      self.action = bound;
+      return Ok(Trans::None);
+    };
+
+    ctx.input.bindings.unbind(&action);
+    ctx.input.bindings.bind(&action, button);
+    // so the press doesn't also go to the pause once this pops
+    ctx.input.take(&action);
+    ctx.input.bindings.save(BINDINGS_PATH)?;
+    Ok(Trans::Pop)
+  }
+
+  fn render(&mut self, ctx: &mut Ctx, _tick_delta: f32) -> Result<Trans, String> {
+    ctx.win.set_title(&format!("hana | rebind | {}", self.prompt));
+    Ok(Trans::None)
+  }
+
+  fn overlay(&self) -> bool {
+    true
+  }
+}
+
+pub struct Gameplay {
+  options: Options,
+  recording: Option<Recording>,
+  // how the replay is going, shown in the title
+  replay_status: Option<String>,
+
+  palette: Palette,
+  defer: Shader,
+  defer_skinned: Shader,
+  defer_indirect: Shader,
+  fin: Shader,
+  blit: Shader,
+  cel: Shader,
+  f_buf: Fbo,
+  g_buf: Fbo,
+  // post processing vertex array
+  p_vao: Vao,
+
+  cam: Camera,
+  world: World,
+  player: Rc<RefCell<Object>>,
+  windmill: Rc<RefCell<Object>>,
+  // whether the windmill is on its spin clip
+  spinning: bool,
+
+  picker: Picker,
+  // the pick action went off, picked once the g buffer is drawn
+  want_pick: bool,
+  // what the last pick found, shown in the title
+  picked: String,
+  shot: CamPath,
+  // what the last record_key, save_path or load_path did, shown in the title
+  shot_status: String,
+  // seconds into the shot while playing it back
+  playing: Option<f32>,
+  // frames rendered of options.render
+  frame: usize,
+  // which one the projection action is on
+  projection: usize
+}
+
+impl Gameplay {
+  pub fn new(ctx: &mut Ctx, options: Options) -> Result<Gameplay, String> {
+    let (width, height) = (ctx.width, ctx.height);
+    // replays have to build the same world
+    let seed = options.replay.as_ref().map_or_else(rand::random, |it| it.recording.seed);
+    let recording = options.record.as_ref().map(|_| Recording::new(seed));
+    let replay_status = options.replay.as_ref().map(|_| "playing".to_string());
+
+    // palette
+    let palette = Palette::new(&[
+      hex_to_vec3(0x66ffe3), hex_to_vec3(0x4da6ff), hex_to_vec3(0x4b5bab), hex_to_vec3(0x473b78), // blue
+      hex_to_vec3(0xcfff70), hex_to_vec3(0x8fde5d), hex_to_vec3(0x3ca370), hex_to_vec3(0x3d6e70), // green
+      hex_to_vec3(0xffe478), hex_to_vec3(0xf2a65e), hex_to_vec3(0xba6156), hex_to_vec3(0x8c3f5d), // yellow
+      hex_to_vec3(0xffb570), hex_to_vec3(0xff9166), hex_to_vec3(0xeb564b), hex_to_vec3(0xb0305c), // orange
+      hex_to_vec3(0xff6b97), hex_to_vec3(0xbd4882), hex_to_vec3(0x80366b), hex_to_vec3(0x5a265e), // pink
+      hex_to_vec3(0xffffeb), hex_to_vec3(0xc2c2d1), hex_to_vec3(0x7e7e8f), hex_to_vec3(0x606070), // white
+    ]);
+
+    // frame buffers
+    let f_buf =
+      Fbo::new(&[
+        (gl::COLOR_ATTACHMENT0, TexSpec::rgba8_linear(width * 2, height * 2)),
+        (gl::DEPTH_ATTACHMENT, TexSpec::depth24_nearest(width * 2, height * 2)),
+      ]);
+
+    let g_buf =
+      Fbo::new(&[
+        (gl::COLOR_ATTACHMENT0, TexSpec::rgba16_linear(width * 2, height * 2)), // POS
+        (gl::COLOR_ATTACHMENT1, TexSpec::rgba16_linear(width * 2, height * 2)), // NORM
+        (gl::COLOR_ATTACHMENT2, TexSpec::rg8_nearest(width * 2, height * 2)), // COLOR
+        (ID_ATTACHMENT, TexSpec::r32ui_nearest(width * 2, height * 2)), // ID
+        (gl::DEPTH_ATTACHMENT, TexSpec::depth32f_nearest(width * 2, height * 2)), // DEPTH, float for reversed z
+      ]);
+
+    // character model
+    let import = ImportOptions { normals: Normals::Smooth(60.), outline_normals: true, ..ImportOptions::default() };
+    let hana = Model::load_with("res/model/hana.obj", &palette, &import)?;
+
+    // world
+    let mut world = World::new();
+    let player = world.add(Object::Player { transform: Transform::IDENTITY, parent: None, model: Rc::new(hana), anim: Animator::new(), tint: -1 });
+
+    // the ground, a tile per space partition cell so it's culled and batched like everything else
+    let ground = Rc::new(MeshBuilder::new().tint(GREEN).plane(Vec2::splat(16.), 4).model());
+    for x in -8..8 {
+      for z in -8..8 {
+        let pos = Vec3::new(x as f32 * 16. + 8., 0., z as f32 * 16. + 8.);
+        world.add(Object::Any { transform: Transform::from_pos(pos), parent: None, model: ground.clone(), anim: Animator::new(), tint: -1 });
+      }
+    }
+
+    // scattered rocks, all drawn with a few multi draws. mossy where they face up
+    let rock = Rc::new(MeshBuilder::new().sphere(0.5, 6, 4).tint_faces(|_, norm| if norm.y > 0.6 { GREEN } else { WHITE }).model());
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..100000 {
+      let pos = Vec3::new(rng.gen_range(-128., 128.), 0., rng.gen_range(-128., 128.));
+      let rot = Quat::from_rotation_y(rng.gen_range(0., std::f32::consts::TAU));
+      world.add(Object::Any {
+        transform: Transform::new(pos, rot, Vec3::splat(rng.gen_range(0.1, 0.4))),
+        parent: None,
+        model: rock.clone(),
+        anim: Animator::new(),
+        tint: -1
+      });
+    }
+
+    world.add(Object::Any { transform: Transform::from_pos(Vec3::new(0., 0., 6.)), parent: None, model: Rc::new(props().model()), anim: Animator::new(), tint: -1 });
+
+    // faceted, to go with the generated props
+    let monkey = Model::load_with("res/model/monkey.obj", &palette, &ImportOptions { normals: Normals::Flat, ..ImportOptions::default() })?;
+    world.add(Object::Any { transform: Transform::from_pos(Vec3::new(-6., 1., 10.)), parent: None, model: Rc::new(monkey), anim: Animator::new(), tint: PINK });
+
+    let windmill = world.add(Object::Any { transform: Transform::from_pos(Vec3::new(6., 0., 10.)), parent: None, model: Rc::new(windmill()), anim: Animator::new(), tint: -1 });
+    windmill.borrow_mut().play("idle", true)?;
+    windmill.borrow_mut().morph("furl", 1., 0)?;
+    windmill.borrow_mut().blend("sway", true, 0.5)?;
+
+    let (p_vao, p_vbo) = gl_gen_v(&[FLOAT_2]);
+    p_vbo.data(
+      gl::STATIC_DRAW,
+      &[
+        -1.0f32, -1.,
+        -1., 1.,
+        1., 1.,
+        1., 1.,
+        1., -1.,
+        -1., -1.
+      ]
+    );
+
+    Ok(Gameplay {
+      options,
+      recording,
+      replay_status,
+      palette,
+      defer: Shader::new("res/shader/model.vert", "res/shader/g_buffer_cel.frag", None)?,
+      defer_skinned: Shader::new("res/shader/skinned.vert", "res/shader/g_buffer_cel.frag", None)?,
+      defer_indirect: Shader::new("res/shader/indirect.vert", "res/shader/g_buffer_cel.frag", None)?,
+      fin: Shader::new("res/shader/postprocess.vert", "res/shader/final_cel.frag", None)?,
+      blit: Shader::new("res/shader/postprocess.vert", "res/shader/blit.frag", None)?,
+      cel: Shader::new("res/shader/postprocess.vert", "res/shader/cel.frag", None)?,
+      f_buf,
+      g_buf,
+      p_vao,
+      cam: Camera::new(),
+      world,
+      player,
+      windmill,
+      spinning: false,
+      picker: Picker::new(),
+      want_pick: false,
+      picked: "-".into(),
+      shot: CamPath::new(Spline::CatmullRom),
+      shot_status: "empty".into(),
+      playing: None,
+      frame: 0,
+      projection: 0
+    })
+  }
+
+  // the camera, shot and carry actions
+  fn controls(&mut self, ctx: &Ctx) -> Result<(), String> {
+    let (cam, input) = (&mut self.cam, &ctx.input);
+    if self.playing.is_none() {
+      cam.mouse_move(input.axis("look_x"), input.axis("look_y"));
+      cam.steer(Vec3::new(input.axis("move_x"), input.axis("move_y"), input.axis("move_z")), input.held("sprint"));
+    }
+
+    // flips between flying around and orbiting the player
+    if input.pressed("orbit") {
+      cam.set_mode(match cam.mode {
+        Mode::Free => Mode::Orbit(Orbit::new(4.)),
+        Mode::Orbit(_) => Mode::Free
+      });
+    }
+
+    // perspective -> reversed z -> isometric -> dimetric
+    if input.pressed("projection") {
+      self.projection = (self.projection + 1) % 4;
+      match self.projection {
+        0 => cam.projection = Projection::Perspective,
+        1 => cam.projection = Projection::ReversedZ,
+        2 => cam.axonometric(ISOMETRIC, 20.),
+        _ => cam.axonometric(DIMETRIC, 20.)
+      }
+    }
+
+    // a key two seconds after the last one, eased in and out
+    if input.pressed("record_key") {
+      let time = if self.shot.keys.is_empty() { 0. } else { self.shot.duration() + 2. };
+      self.shot.record(cam, time, Ease::InOut);
+      self.shot_status = format!("{} keys", self.shot.keys.len());
+    }
+
+    if input.pressed("play_path") {
+      self.playing = match self.playing {
+        None if !self.shot.keys.is_empty() => Some(0.),
+        _ => None
+      };
+    }
+
+    if input.pressed("save_path") {
+      self.shot_status = match self.shot.save(SHOT_PATH) {
+        Ok(_) => format!("saved {} keys", self.shot.keys.len()),
+        Err(e) => e
+      };
+    }
+
+    if input.pressed("load_path") {
+      self.shot_status = match CamPath::load(SHOT_PATH) {
+        Ok(it) => {
+          self.shot = it;
+          format!("loaded {} keys", self.shot.keys.len())
+        }
+        Err(e) => e
+      };
+    }
+
+    self.want_pick |= input.pressed("pick");
+
+    // picks up what's under the crosshair while carry is held, and puts it down once it's let go.
+    // either way it stays where it is until the player moves
+    if input.released("carry") {
+      for it in self.world.children(&self.player) {
+        let world = it.borrow().world_matrix();
+        *it.borrow_mut().transform_mut() = Transform::from_mat4(&world);
+        self.world.detach(&it);
+      }
+    }
+
+    if input.pressed("carry") {
+      if let Some(aim) = self.world.raycast_except(&Ray::new(cam.pos, cam.front), 10., &self.player) {
+        let local = self.player.borrow().world_matrix().inverse() * aim.obj.borrow().world_matrix();
+        *aim.obj.borrow_mut().transform_mut() = Transform::from_mat4(&local);
+        self.world.attach(&aim.obj, &self.player)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl State for Gameplay {
+  fn init(&mut self, ctx: &mut Ctx) -> Result<(), String> {
+    ctx.win.set_cursor_mode(CursorMode::Disabled);
+    Ok(())
+  }
+
+  fn input(&mut self, ctx: &mut Ctx) -> Result<Trans, String> {
+    if ctx.input.take("step") {
+      ctx.game.step();
+    }
+
+    Ok(if ctx.input.take("pause") { Trans::Push(Box::new(Pause)) } else { Trans::None })
+  }
+
+  fn tick(&mut self, ctx: &mut Ctx) -> Result<Trans, String> {
+    let frame = self.options.replay.as_mut().and_then(Replay::next);
+    if let (Some(it), None) = (&self.options.replay, &frame) {
+      let diverged = it.diverged();
+      self.options.replay = None;
+      self.replay_status = Some(diverged.map_or("every tick matched".into(), |it| format!("diverged at tick {}", it)));
+      if self.options.verify {
+        return diverged.map_or(Ok(Trans::Quit), |it| Err(format!("replay diverged at tick {}", it)));
+      }
+    }
+
+    // None hands control back once the replay is over
+    ctx.input.play(frame);
+    self.controls(ctx)?;
+    let frame = self.recording.as_ref().map(|_| ctx.input.frame());
+
+    self.cam.tick();
+
+    self.world.tick(self.cam.pos, 4);
+
+    // unfurls and spins up as the camera comes close, winds back down once it leaves
+    let near = self.windmill.borrow().world_pos().distance(self.cam.pos) < WINDMILL_RANGE;
+    if near != self.spinning {
+      self.spinning = near;
+      let mut windmill = self.windmill.borrow_mut();
+      windmill.crossfade(if near { "spin" } else { "idle" }, true, 20)?;
+      windmill.morph("furl", if near { 0. } else { 1. }, 20)?;
+    }
+    let (world, player) = (&self.world, &self.player);
+    self.cam.follow(player.borrow().world_pos(), |ray, max_t| world.raycast_except(ray, max_t, player).map(|it| it.hit.t));
+
+    if let Some(time) = &mut self.playing {
+      *time += TICK_LENGTH;
+      match self.shot.sample(*time) {
+        Some(pose) if *time <= self.shot.duration() => self.cam.set_pose(&pose),
+        _ => self.playing = None
+      }
+    }
+
+    // only worth hashing the world for when something compares it
+    if self.recording.is_some() || self.options.replay.is_some() {
+      let sum = checksum(&self.world, &self.cam);
+      if let (Some(recording), Some(frame)) = (&mut self.recording, frame) {
+        recording.ticks.push((frame, sum));
+      }
+
+      if let Some(it) = &mut self.options.replay {
+        let first = it.diverged().is_none();
+        if !it.check(sum) && first {
+          self.replay_status = Some(format!("diverged at tick {}, playing on", it.diverged().unwrap_or_default()));
+        }
+      }
+    }
+
+    Ok(Trans::None)
+  }
+
+  fn render(&mut self, ctx: &mut Ctx, tick_delta: f32) -> Result<Trans, String> {
+    if self.options.verify {
+      return Ok(Trans::None);
+    }
+
+    let (width, height) = (ctx.width, ctx.height);
+    let cam = &mut self.cam;
+    if let Some((shot, _, fps)) = &self.options.render {
+      if let Some(pose) = shot.sample(self.frame as f32 / fps) {
+        cam.cut(&pose);
+      }
+    }
+
+    if let Some(pick) = self.picker.poll() {
+      self.picked = match self.world.by_id(pick.id) {
+        Some(obj) => {
+          // a knock, closer hits knock harder
+          cam.shake.add((1. - pick.pos.distance(cam.pos) / 50.).clamp(0.1, 0.5));
+          format!("#{} at {:.1}", pick.id, obj.borrow().world_pos())
+        }
+        None => "nothing".into()
+      };
+    }
+
+    // set up per-frame gl state
+    gl_enable(gl::DEPTH_TEST);
+    gl_disable(gl::BLEND);
+
+    // begin g buffer pass
+    gl_viewport(width * 2, height * 2);
+    self.g_buf.bind();
+    self.g_buf.draw_buffers(&G_DRAW_BUFFERS);
+    let (depth_func, clear_depth) = cam.projection.depth();
+    gl_depth_func(depth_func);
+    gl_clear_depth(clear_depth);
+    gl_clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    let id_buffer = G_DRAW_BUFFERS.iter().position(|it| *it == ID_ATTACHMENT).ok_or("no id attachment")?;
+    self.g_buf.clear_ui(id_buffer as i32, NO_ID);
+
+    for it in [&self.defer_indirect, &self.defer_skinned, &self.defer] {
+      it.bind();
+      it.set_mat4("u_proj", &cam.proj(width as f32 / height as f32));
+      it.set_mat4("u_look", &cam.look_at(tick_delta));
+      it.set_1i("tint", 5);
+    }
+
+    let aspect = width as f32 / height as f32;
+    let stats = self.world.draw(&self.defer, &self.defer_skinned, &self.defer_indirect, tick_delta, &cam.view(aspect, tick_delta), 4);
+    // outlined in the darkest ramp so the player stands out
+    let player = self.player.borrow();
+    self.defer.bind();
+    self.defer.set_1i("u_tint_override", BLUE);
+    player.model().draw_outline(&self.defer, &player.world_matrix(), 0.02);
+    self.defer.set_1i("u_tint_override", -1);
+    drop(player);
+
+    // whatever is under the crosshair
+    let size = Vec2::new(width as f32, height as f32);
+    let aim = self.world.raycast(&cam.ray(size * 0.5, size, tick_delta), 100.);
+    ctx.win.set_title(&format!(
+      "hana | objects {}/{} | meshes {}/{} | tris {} | draws {} | aim {} | picked {} | path {} | dropped ticks {}{}{}",
+      stats.objects_drawn, stats.objects_drawn + stats.objects_culled,
+      stats.meshes_drawn, stats.meshes_drawn + stats.meshes_culled,
+      stats.triangles_drawn, stats.draw_calls,
+      aim.map_or("-".into(), |it| format!("{:.1}m mesh {} tri {}", it.hit.t, it.mesh, it.hit.tri)),
+      self.picked, self.shot_status,
+      ctx.game.dropped, self.replay_status.as_ref().map_or("".into(), |it| format!(" | replay {}", it)),
+      if ctx.game.paused { " | paused" } else { "" }
+    ));
+    gl_depth_func(gl::LESS);
+    gl_clear_depth(1.);
+
+    // whatever is under the cursor, or the crosshair while the cursor is captured
+    if self.want_pick {
+      self.want_pick = false;
+      let (x, y) = if ctx.win.get_cursor_mode() == CursorMode::Disabled {
+        (width as f64 / 2., height as f64 / 2.)
+      } else {
+        ctx.win.get_cursor_pos()
+      };
+
+      self.picker.request(&self.g_buf, IVec2::new(x as i32 * 2, (height - y as i32) * 2 - 1));
+    }
+    // end g buffer pass
+
+    // begin lighting pass
+    let fin = &self.fin;
+    gl_viewport(width * 2, height * 2);
+    self.f_buf.bind();
+    gl_clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    fin.bind();
+    self.g_buf.tex_at(gl::COLOR_ATTACHMENT0).bind(gl::TEXTURE0);
+    fin.set_1i("f_pos", 0);
+    self.g_buf.tex_at(gl::COLOR_ATTACHMENT1).bind(gl::TEXTURE1);
+    fin.set_1i("f_norm", 1);
+    self.g_buf.tex_at(gl::COLOR_ATTACHMENT2).bind(gl::TEXTURE2);
+    fin.set_1i("f_tint", 2);
+    fin.set_4f("u_eye", &cam.eye_or_dir(tick_delta));
+    fin.set_3fv("palette", &self.palette.0);
+    self.p_vao.bind();
+    gl_draw_arrays(gl::TRIANGLES, 6);
+    // end lighting pass
+
+    // begin blitting to backbuffer with cel shading
+    gl_viewport(width, height);
+    ctx.win.fbo0().bind();
+    gl_clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    self.blit.bind();
+    self.f_buf.tex_at(gl::COLOR_ATTACHMENT0).bind(gl::TEXTURE0);
+    self.cel.set_1i("u_tex", 0);
+    self.p_vao.bind();
+    gl_draw_arrays(gl::TRIANGLES, 6);
+    // end blitting to backbuffer
+
+    if let Some((shot, dir, fps)) = &self.options.render {
+      let pixels = ctx.win.fbo0().read_rgba8(gl::BACK, width, height);
+      let img = image::RgbaImage::from_raw(width as u32, height as u32, pixels).ok_or("frame doesn't match the window")?;
+      let file = format!("{}/{:05}.png", dir, self.frame);
+      image::imageops::flip_vertical(&img).save(&file).map_err(|e| file.clone() + ": " + &e.to_string())?;
+      self.frame += 1;
+      if self.frame as f32 / fps > shot.duration() {
+        return Ok(Trans::Quit);
+      }
+    }
+
+    Ok(Trans::None)
+  }
+
+  fn event(&mut self, _ctx: &mut Ctx, event: &WindowEvent) {
+    match *event {
+      WindowEvent::Scroll(_, y) => self.cam.zoom(y as f32),
+      WindowEvent::Size(width, height) => {
+        self.f_buf.resize_attachments(&[gl::COLOR_ATTACHMENT0, gl::DEPTH_ATTACHMENT], width * 2, height * 2);
+        self.g_buf.resize_attachments(
+          &[gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1, gl::COLOR_ATTACHMENT2, ID_ATTACHMENT, gl::DEPTH_ATTACHMENT],
+          width * 2,
+          height * 2
+        );
+      }
+      _ => {}
+    }
+  }
+
+  fn exit(&mut self, _ctx: &mut Ctx) -> Result<(), String> {
+    match (&self.options.record, &self.recording) {
+      (Some(path), Some(recording)) => recording.save(path),
+      _ => Ok(())
+    }
+  }
+}
@@ -5,4 +5,22 @@ pub(crate) mod cvt;
 pub(crate) mod world;
 pub(crate) mod entity;
 pub(crate) mod palette;
+pub(crate) mod transform;
+pub(crate) mod anim;
+pub(crate) mod bounds;
+pub(crate) mod cache;
+pub(crate) mod lod;
+pub(crate) mod opt;
+pub(crate) mod batch;
+pub(crate) mod pool;
+pub(crate) mod builder;
+pub(crate) mod normals;
+pub(crate) mod ray;
+pub(crate) mod pick;
+pub(crate) mod cine;
+pub(crate) mod shake;
+pub(crate) mod input;
+pub(crate) mod replay;
+pub(crate) mod clock;
+pub(crate) mod app;
 
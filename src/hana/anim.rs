@@ -0,0 +1,348 @@
+use glam::{Mat4, Quat, Vec3};
+use crate::hana::model::Model;
+use crate::hana::transform::Transform;
+
+// what the simulation steps by every tick, see clock::GameLoop
+pub const TICK_LENGTH: f32 = 1. / 30.;
+
+pub struct Bone {
+  pub name: String,
+  pub node: usize,
+  // mesh space to bone space in the bind pose
+  pub offset: Mat4
+}
+
+pub struct Channel {
+  pub node: usize,
+  pub pos: Vec<(f32, Vec3)>,
+  pub rot: Vec<(f32, Quat)>,
+  pub scale: Vec<(f32, Vec3)>
+}
+
+// key times are in seconds
+pub struct Clip {
+  pub name: String,
+  pub duration: f32,
+  pub channels: Vec<Channel>
+}
+
+fn sample_keys<T: Copy>(keys: &[(f32, T)], time: f32, mix: impl Fn(T, T, f32) -> T) -> Option<T> {
+  let next = keys.partition_point(|it| it.0 <= time);
+  if next == 0 {
+    return keys.first().map(|it| it.1);
+  }
+
+  if next == keys.len() {
+    return keys.last().map(|it| it.1);
+  }
+
+  let (t0, a) = keys[next - 1];
+  let (t1, b) = keys[next];
+  Some(mix(a, b, (time - t0) / (t1 - t0)))
+}
+
+impl Clip {
+  pub fn wrap(&self, time: f32, looping: bool) -> f32 {
+    if self.duration <= 0. {
+      return 0.;
+    }
+
+    if looping {
+      time.rem_euclid(self.duration)
+    } else {
+      time.clamp(0., self.duration)
+    }
+  }
+
+  // overwrites the locals of every node this clip animates
+  pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+    for channel in &self.channels {
+      let local = &mut pose[channel.node];
+      if let Some(pos) = sample_keys(&channel.pos, time, Vec3::lerp) {
+        local.pos = pos;
+      }
+
+      if let Some(rot) = sample_keys(&channel.rot, time, Quat::slerp) {
+        local.rot = rot;
+      }
+
+      if let Some(scale) = sample_keys(&channel.scale, time, Vec3::lerp) {
+        local.scale = scale;
+      }
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+pub struct Playback {
+  pub clip: usize,
+  pub time: f32,
+  pub speed: f32,
+  pub looping: bool
+}
+
+impl Playback {
+  pub fn new(clip: usize, looping: bool) -> Playback {
+    Playback { clip, time: 0., speed: 1., looping }
+  }
+
+  fn sample(&self, model: &Model, tick_delta: f32, pose: &mut [Transform]) {
+    let clip = &model.clips[self.clip];
+    clip.sample(clip.wrap(self.time + tick_delta * TICK_LENGTH * self.speed, self.looping), pose);
+  }
+}
+
+struct Fade {
+  from: Playback,
+  ticks: u32,
+  elapsed: u32
+}
+
+// eases towards target by step every tick
+#[derive(Clone, Copy, Default)]
+struct MorphWeight {
+  prev: f32,
+  cur: f32,
+  target: f32,
+  step: f32
+}
+
+// steps clips on the fixed tick, poses are sampled between ticks with tick_delta
+pub struct Animator {
+  pub current: Option<Playback>,
+  // blended on top of current with a fixed weight, e.g. walk -> run. nodes its clip doesn't
+  // animate are left as current has them
+  pub layer: Option<(Playback, f32)>,
+  fade: Option<Fade>,
+  // per Model::morphs
+  morphs: Vec<MorphWeight>
+}
+
+impl Animator {
+  pub fn new() -> Animator {
+    Animator { current: None, layer: None, fade: None, morphs: Vec::new() }
+  }
+
+  pub fn play(&mut self, clip: usize, looping: bool) {
+    self.current = Some(Playback::new(clip, looping));
+    self.fade = None;
+  }
+
+  pub fn crossfade(&mut self, clip: usize, looping: bool, ticks: u32) {
+    let Some(from) = self.current else {
+      return self.play(clip, looping);
+    };
+
+    self.current = Some(Playback::new(clip, looping));
+    self.fade = Some(Fade { from, ticks: ticks.max(1), elapsed: 0 });
+  }
+
+  pub fn blend(&mut self, clip: usize, looping: bool, weight: f32) {
+    self.layer = Some((Playback::new(clip, looping), weight.clamp(0., 1.)));
+  }
+
+  // reaches `weight` in `ticks`, 0 ticks snaps straight to it
+  pub fn morph(&mut self, slot: usize, weight: f32, ticks: u32) {
+    if self.morphs.len() <= slot {
+      self.morphs.resize(slot + 1, MorphWeight::default());
+    }
+
+    let it = &mut self.morphs[slot];
+    it.target = weight;
+    if ticks == 0 {
+      *it = MorphWeight { prev: weight, cur: weight, target: weight, step: 0. };
+    } else {
+      it.step = (weight - it.cur).abs() / ticks as f32;
+    }
+  }
+
+  // whether any morph is away from the base shape, those objects can't be instanced
+  pub fn morphing(&self) -> bool {
+    self.morphs.iter().any(|it| it.prev != 0. || it.cur != 0.)
+  }
+
+  pub fn morph_weights(&self, tick_delta: f32) -> Vec<f32> {
+    self.morphs.iter().map(|it| it.prev + (it.cur - it.prev) * tick_delta).collect()
+  }
+
+  pub fn tick(&mut self) {
+    for it in [self.current.as_mut(), self.layer.as_mut().map(|it| &mut it.0)].into_iter().flatten() {
+      it.time += TICK_LENGTH * it.speed;
+    }
+
+    for it in &mut self.morphs {
+      it.prev = it.cur;
+      it.cur = if it.cur < it.target { (it.cur + it.step).min(it.target) } else { (it.cur - it.step).max(it.target) };
+    }
+
+    if let Some(fade) = &mut self.fade {
+      fade.from.time += TICK_LENGTH * fade.from.speed;
+      fade.elapsed += 1;
+      if fade.elapsed >= fade.ticks {
+        self.fade = None;
+      }
+    }
+  }
+
+  pub fn pose(&self, model: &Model, tick_delta: f32) -> Vec<Transform> {
+    let bind = model.bind_pose();
+    let Some(current) = self.current else {
+      return bind;
+    };
+
+    let mut pose = bind.clone();
+    current.sample(model, tick_delta, &mut pose);
+
+    if let Some((layer, weight)) = self.layer {
+      let mut other = pose.clone();
+      layer.sample(model, tick_delta, &mut other);
+      blend_poses(&mut pose, &other, weight);
+    }
+
+    if let Some(fade) = &self.fade {
+      let mut from = bind;
+      fade.from.sample(model, tick_delta, &mut from);
+      let t = ((fade.elapsed as f32 + tick_delta) / fade.ticks as f32).min(1.);
+      blend_poses(&mut from, &pose, t);
+      return from;
+    }
+
+    pose
+  }
+}
+
+impl Default for Animator {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+pub fn blend_poses(a: &mut [Transform], b: &[Transform], t: f32) {
+  for (a, b) in a.iter_mut().zip(b) {
+    *a = a.lerp(b, t);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hana::model::Node;
+
+  fn near(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+  }
+
+  // x of node 0 is the clip's value, so poses read straight off as a weight
+  fn model() -> Model {
+    let node = |name: &str, parent| Node { name: name.into(), transform: Mat4::IDENTITY, parent, meshes: Vec::new() };
+    let hold = |name: &str, node, x| Clip { name: name.into(), duration: 1., channels: vec![Channel { node, pos: vec![(0., Vec3::X * x)], rot: Vec::new(), scale: Vec::new() }] };
+    Model::assemble(Vec::new(), Vec::new(), vec![node("a", None), node("b", Some(0))], Vec::new(), vec![hold("zero", 0, 0.), hold("one", 0, 1.), hold("other", 1, 1.)])
+  }
+
+  #[test]
+  fn sample_keys_clamps_past_either_end() {
+    let keys = [(1., 10.), (2., 20.), (4., 40.)];
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    assert_eq!(sample_keys(&keys, 0., lerp), Some(10.));
+    assert_eq!(sample_keys(&keys, 1., lerp), Some(10.));
+    assert_eq!(sample_keys(&keys, 1.5, lerp), Some(15.));
+    assert_eq!(sample_keys(&keys, 2., lerp), Some(20.));
+    assert_eq!(sample_keys(&keys, 3., lerp), Some(30.));
+    assert_eq!(sample_keys(&keys, 4., lerp), Some(40.));
+    assert_eq!(sample_keys(&keys, 5., lerp), Some(40.));
+    assert_eq!(sample_keys(&keys[..1], 5., lerp), Some(10.));
+    assert_eq!(sample_keys(&[], 1., lerp), None);
+  }
+
+  #[test]
+  fn wrap_loops_or_clamps() {
+    let clip = Clip { name: "clip".into(), duration: 2., channels: Vec::new() };
+    assert!(near(clip.wrap(0.5, true), 0.5));
+    assert!(near(clip.wrap(2.5, true), 0.5));
+    assert!(near(clip.wrap(-0.5, true), 1.5));
+    assert!(near(clip.wrap(2.5, false), 2.));
+    assert!(near(clip.wrap(-0.5, false), 0.));
+    assert_eq!(Clip { duration: 0., ..clip }.wrap(3., true), 0.);
+  }
+
+  #[test]
+  fn crossfade_weight_goes_linearly_over_its_ticks() {
+    let model = model();
+    let mut anim = Animator::new();
+    anim.play(0, true);
+    anim.crossfade(1, true, 4);
+
+    assert!(near(anim.pose(&model, 0.)[0].pos.x, 0.));
+    assert!(near(anim.pose(&model, 0.5)[0].pos.x, 0.125));
+    anim.tick();
+    assert!(near(anim.pose(&model, 0.)[0].pos.x, 0.25));
+    anim.tick();
+    anim.tick();
+    assert!(near(anim.pose(&model, 0.5)[0].pos.x, 0.875));
+    anim.tick();
+    assert!(anim.fade.is_none());
+    assert!(near(anim.pose(&model, 0.)[0].pos.x, 1.));
+  }
+
+  #[test]
+  fn crossfade_without_a_clip_plays() {
+    let model = model();
+    let mut anim = Animator::new();
+    anim.crossfade(1, true, 4);
+    assert!(anim.fade.is_none());
+    assert!(near(anim.pose(&model, 0.)[0].pos.x, 1.));
+  }
+
+  #[test]
+  fn blend_weights_the_layer_and_leaves_the_nodes_it_doesnt_animate() {
+    let model = model();
+    let mut anim = Animator::new();
+    anim.play(0, true);
+    anim.blend(1, true, 0.25);
+    assert!(near(anim.pose(&model, 0.)[0].pos.x, 0.25));
+
+    anim.play(1, true);
+    anim.blend(2, true, 0.5);
+    let pose = anim.pose(&model, 0.);
+    assert!(near(pose[0].pos.x, 1.));
+    assert!(near(pose[1].pos.x, 0.5));
+
+    anim.blend(1, true, 2.);
+    assert_eq!(anim.layer.map(|it| it.1), Some(1.));
+  }
+
+  #[test]
+  fn morphs_ease_over_their_ticks() {
+    let mut anim = Animator::new();
+    assert!(!anim.morphing());
+    anim.morph(1, 1., 4);
+    assert_eq!(anim.morph_weights(0.5), [0., 0.]);
+
+    anim.tick();
+    assert!(anim.morphing());
+    assert!(near(anim.morph_weights(0.)[1], 0.));
+    assert!(near(anim.morph_weights(0.5)[1], 0.125));
+    assert!(near(anim.morph_weights(1.)[1], 0.25));
+    for _ in 0..3 {
+      anim.tick();
+    }
+
+    assert!(near(anim.morph_weights(1.)[1], 1.));
+    // and stays there
+    anim.tick();
+    anim.tick();
+    assert!(near(anim.morph_weights(0.5)[1], 1.));
+
+    // back down from wherever it is, at the rate that gets there in time
+    anim.morph(1, 0.5, 2);
+    anim.tick();
+    assert!(near(anim.morph_weights(1.)[1], 0.75));
+    anim.tick();
+    anim.tick();
+    assert!(near(anim.morph_weights(1.)[1], 0.5));
+
+    anim.morph(1, 0., 0);
+    assert_eq!(anim.morph_weights(0.5), [0., 0.]);
+    assert!(!anim.morphing());
+  }
+}
@@ -0,0 +1,265 @@
+use std::sync::mpsc::Receiver;
+use glfw::{Action, Context, CursorMode, Glfw, Key, MouseButton, SwapInterval, Window, WindowEvent, WindowHint};
+use crate::hana::clock::{Clock, FakeClock, GameLoop, Hooks};
+use crate::hana::glu::*;
+use crate::hana::input::{Bindings, Input};
+
+// what a state wants done to the stack. always done to the top of it, whichever state asked
+pub enum Trans {
+  None,
+  // on top, the ones under it wait
+  Push(Box<dyn State>),
+  Pop,
+  // pop and push
+  Switch(Box<dyn State>),
+  Quit
+}
+
+// one screen of the game, e.g. a menu, the game itself or a pause overlay. the engine keeps them on a stack
+pub trait State {
+  // once it's pushed
+  fn init(&mut self, _ctx: &mut Ctx) -> Result<(), String> {
+    Ok(())
+  }
+
+  // once a frame before ticking, top state only. for things outside the fixed tick like pausing
+  fn input(&mut self, _ctx: &mut Ctx) -> Result<Trans, String> {
+    Ok(Trans::None)
+  }
+
+  // on the top state that isn't an overlay
+  fn tick(&mut self, _ctx: &mut Ctx) -> Result<Trans, String> {
+    Ok(Trans::None)
+  }
+
+  // from the top state that isn't an overlay up, so overlays draw over what they cover
+  fn render(&mut self, _ctx: &mut Ctx, _tick_delta: f32) -> Result<Trans, String> {
+    Ok(Trans::None)
+  }
+
+  // every state gets every window event, covered ones still need to know about resizes
+  fn event(&mut self, _ctx: &mut Ctx, _event: &WindowEvent) {}
+
+  // popped, switched away from, or still on the stack when the engine stops
+  fn exit(&mut self, _ctx: &mut Ctx) -> Result<(), String> {
+    Ok(())
+  }
+
+  // lets ticks and drawing through to the state under it
+  fn overlay(&self) -> bool {
+    false
+  }
+}
+
+// what states get to use
+pub struct Ctx {
+  pub glfw: Glfw,
+  pub win: Window,
+  // window size, the framebuffers the game draws into are up to it
+  pub width: i32,
+  pub height: i32,
+  pub input: Input,
+  pub game: GameLoop,
+  // read instead of glfw's time when set
+  pub clock: Option<FakeClock>
+}
+
+// owns the window and gl context, and runs a stack of states on the fixed tick
+pub struct Engine {
+  pub ctx: Ctx,
+  events: Receiver<(f64, WindowEvent)>,
+  stack: Vec<Box<dyn State>>,
+  // where the cursor was while it's captured, None until it moves after being captured
+  cursor: Option<(f64, f64)>,
+  // false once a state quits or the stack empties, the hooks do nothing after that or an error
+  running: Result<bool, String>
+}
+
+impl Engine {
+  // a hidden window still gets a context, for rendering offscreen
+  pub fn new(title: &str, width: i32, height: i32, visible: bool, bindings: Bindings) -> Result<Engine, String> {
+    let mut glfw = glfw::init(glfw::fail_on_errors).map_err(|e| e.to_string())?;
+    glfw.window_hint(WindowHint::Visible(visible));
+    glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    glfw.window_hint(WindowHint::ContextVersion(4, 6));
+    glfw.window_hint(WindowHint::Resizable(true));
+    glfw.window_hint(WindowHint::Samples(Some(4)));
+
+    let (mut win, events) =
+      glfw
+        .create_window(width as u32, height as u32, title, glfw::WindowMode::Windowed)
+        .ok_or("failed to make window.")?;
+
+    win.make_current();
+    win.set_cursor_pos_polling(true);
+    win.set_mouse_button_polling(true);
+    win.set_size_polling(true);
+    win.set_key_polling(true);
+    win.set_scroll_polling(true);
+    glfw.set_swap_interval(SwapInterval::Adaptive);
+
+    gl::load_with(|s| win.get_proc_address(s) as *const _);
+    glfw.make_context_current(Some(&win));
+
+    // set up permanent gl state
+    gl_enable(gl::MULTISAMPLE);
+    gl_clear_color(0.0, 0.0, 0.0, 0.0);
+    gl_depth_func(gl::LESS);
+    gl_enable(gl::DEPTH_TEST);
+    // glam's projections put depth in 0..1
+    gl_clip_control(gl::LOWER_LEFT, gl::ZERO_TO_ONE);
+
+    let ctx = Ctx { glfw, win, width, height, input: Input::new(bindings), game: GameLoop::default(), clock: None };
+    Ok(Engine { ctx, events, stack: Vec::new(), cursor: None, running: Ok(true) })
+  }
+
+  // until the window closes, the stack empties or a state quits. whatever is left on the stack exits either way
+  pub fn run(mut self, first: Box<dyn State>) -> Result<(), String> {
+    let mut res = self.push(first).and_then(|_| self.frames());
+    while let Some(mut it) = self.stack.pop() {
+      res = res.and(it.exit(&mut self.ctx));
+    }
+
+    res
+  }
+
+  fn push(&mut self, mut state: Box<dyn State>) -> Result<(), String> {
+    state.init(&mut self.ctx)?;
+    self.stack.push(state);
+    Ok(())
+  }
+
+  fn pop(&mut self) -> Result<(), String> {
+    match self.stack.pop() {
+      Some(mut it) => it.exit(&mut self.ctx),
+      None => Ok(())
+    }
+  }
+
+  // false once there's nothing left to run
+  fn apply(&mut self, trans: Trans) -> Result<bool, String> {
+    match trans {
+      Trans::None => {}
+      Trans::Push(it) => self.push(it)?,
+      Trans::Pop => self.pop()?,
+      Trans::Switch(it) => {
+        self.pop()?;
+        self.push(it)?;
+      }
+      Trans::Quit => return Ok(false)
+    }
+
+    Ok(!self.stack.is_empty())
+  }
+
+  // the top state that isn't an overlay
+  fn below_overlays(&self) -> usize {
+    self.stack.iter().rposition(|it| !it.overlay()).unwrap_or(0)
+  }
+
+  fn frames(&mut self) -> Result<(), String> {
+    while !self.ctx.win.should_close() {
+      let now = match &mut self.ctx.clock {
+        Some(it) => it.now(),
+        None => self.ctx.glfw.now()
+      };
+
+      GameLoop::frame(self, now);
+      if !self.running.clone()? {
+        return Ok(());
+      }
+
+      self.ctx.win.swap_buffers();
+
+      self.ctx.glfw.poll_events();
+      let events = glfw::flush_messages(&self.events).map(|(_, it)| it).collect::<Vec<_>>();
+      for event in events {
+        self.event(&event);
+        for it in &mut self.stack {
+          it.event(&mut self.ctx, &event);
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  // runs `f` while nothing has stopped the engine yet
+  fn guard(&mut self, f: impl FnOnce(&mut Engine) -> Result<bool, String>) {
+    if let Ok(true) = self.running {
+      self.running = f(self);
+    }
+  }
+
+  // input and the cursor, escape lets it go and clicking captures it again
+  fn event(&mut self, event: &WindowEvent) {
+    let ctx = &mut self.ctx;
+    ctx.input.event(event);
+    match *event {
+      WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
+        self.cursor = None;
+        ctx.win.set_cursor_mode(CursorMode::Normal);
+      }
+      WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _) => {
+        ctx.win.set_cursor_mode(CursorMode::Disabled);
+      }
+      WindowEvent::Size(width, height) => {
+        ctx.width = width;
+        ctx.height = height;
+        gl_viewport(width, height);
+      }
+      WindowEvent::CursorPos(x, y) => {
+        if ctx.win.get_cursor_mode() != CursorMode::Disabled {
+          return;
+        }
+
+        let (last_x, last_y) = self.cursor.unwrap_or((x, y));
+        ctx.input.mouse_move((x - last_x) as f32, (y - last_y) as f32);
+        self.cursor = Some((x, y));
+      }
+      _ => {}
+    }
+  }
+}
+
+// the stack on the fixed tick
+impl Hooks for Engine {
+  fn game(&mut self) -> &mut GameLoop {
+    &mut self.ctx.game
+  }
+
+  fn input(&mut self) {
+    self.guard(|it| {
+      let top = it.stack.len() - 1;
+      let trans = it.stack[top].input(&mut it.ctx)?;
+      it.apply(trans)
+    });
+  }
+
+  fn tick(&mut self) {
+    self.guard(|it| {
+      it.ctx.input.poll_gamepad(&it.ctx.glfw);
+      let at = it.below_overlays();
+      let trans = it.stack[at].tick(&mut it.ctx)?;
+      it.ctx.input.tick();
+      it.apply(trans)
+    });
+  }
+
+  fn render(&mut self, tick_delta: f32) {
+    self.guard(|it| {
+      let mut done = Vec::new();
+      for at in it.below_overlays()..it.stack.len() {
+        done.push(it.stack[at].render(&mut it.ctx, tick_delta)?);
+      }
+
+      for trans in done {
+        if !it.apply(trans)? {
+          return Ok(false);
+        }
+      }
+
+      Ok(true)
+    });
+  }
+}
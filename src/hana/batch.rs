@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::rc::{Rc, Weak};
+use glam::Mat4;
+use crate::hana::bounds::CullStats;
+use crate::hana::glu::{Buf, gl_multi_draw_elements_indirect, Shader};
+use crate::hana::model::{Mesh, Model};
+use crate::hana::pool;
+use crate::hana::pool::Format;
+
+// one object's worth of a batch, Model::commands turns these into a DrawData per mesh
+#[derive(Clone, Copy)]
+pub struct Instance {
+  pub model: [f32; 16],
+  // overrides the vertex tints when >= 0
+  pub tint: i32,
+  // written to the g buffer for picking, see World::by_id
+  pub id: u32
+}
+
+// matches DrawElementsIndirectCommand
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Command {
+  pub count: u32,
+  pub instance_count: u32,
+  pub first_index: u32,
+  pub base_vertex: i32,
+  pub base_instance: u32
+}
+
+// std430 layout of indirect.vert's Draw, one per instance of every mesh.
+// commands point their base_instance at the first one they use
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DrawData {
+  model: [f32; 16],
+  quant_min: [f32; 4],
+  quant_size: [f32; 4],
+  tint: i32,
+  packed: i32,
+  id: u32,
+  pad: i32
+}
+
+impl DrawData {
+  pub fn new(model: &Mat4, mesh: &Mesh, tint: i32, id: u32) -> DrawData {
+    DrawData {
+      model: model.to_cols_array(),
+      quant_min: mesh.bounds.min.extend(0.).to_array(),
+      quant_size: (mesh.bounds.max - mesh.bounds.min).extend(0.).to_array(),
+      tint,
+      packed: mesh.packed as i32,
+      id,
+      pad: 0
+    }
+  }
+}
+
+// everything sharing a pool and a texture goes into one multi draw
+pub type DrawGroups = HashMap<(Format, Option<u32>), Vec<Command>>;
+
+// keep in sync with indirect.vert
+pub const DRAW_BINDING: u32 = 0;
+
+struct Batch {
+  model: Weak<Model>,
+  // instances per lod level
+  levels: Vec<Vec<Instance>>
+}
+
+// groups instances by model and draws all of them with a multi draw per pool and texture.
+// this is the instanced path too: there are no per-instance attributes with divisors and no
+// glDrawElementsInstanced, each command's instance_count covers every instance of a mesh and
+// indirect.vert finds their transform and tint at gl_BaseInstance + gl_InstanceID
+pub struct Batches {
+  models: HashMap<*const Model, Batch>,
+  // draws then commands, made on the first draw so there's no need for a gl context before that
+  bufs: Option<(Buf, Buf)>
+}
+
+impl Batches {
+  pub fn new() -> Batches {
+    Batches {
+      models: HashMap::new(),
+      bufs: None
+    }
+  }
+
+  pub fn add(&mut self, model: &Rc<Model>, level: usize, instance: Instance) {
+    let batch = self.models.entry(Rc::as_ptr(model)).or_insert_with(|| Batch {
+      model: Rc::downgrade(model),
+      levels: Vec::new()
+    });
+
+    // the old model was dropped and this one landed at its address
+    if batch.model.strong_count() == 0 {
+      batch.model = Rc::downgrade(model);
+    }
+
+    if batch.levels.len() <= level {
+      batch.levels.resize_with(level + 1, Vec::new);
+    }
+
+    batch.levels[level].push(instance);
+  }
+
+  // draws and clears everything added since the last call, needs indirect.vert
+  pub fn draw(&mut self, shader: &Shader, stats: &mut CullStats) {
+    let mut draws = Vec::new();
+    let mut groups = DrawGroups::new();
+    let models = self.models.values().filter_map(|it| Some((it.model.upgrade()?, &it.levels))).collect::<Vec<_>>();
+    for (model, levels) in &models {
+      for (level, instances) in levels.iter().enumerate() {
+        if !instances.is_empty() {
+          model.commands(level, instances, &mut draws, &mut groups, stats);
+        }
+      }
+    }
+
+    if !draws.is_empty() {
+      let textures = models.iter()
+        .flat_map(|(model, _)| model.materials.iter().filter_map(|it| it.tex.as_ref()))
+        .map(|it| (it.id, it))
+        .collect::<HashMap<_, _>>();
+
+      let mut commands = Vec::new();
+      let mut ranges = Vec::new();
+      for (key, it) in &groups {
+        ranges.push((*key, commands.len(), it.len()));
+        commands.extend_from_slice(it);
+      }
+
+      let (draw_buf, command_buf) = self.bufs.get_or_insert_with(|| (Buf::new(gl::SHADER_STORAGE_BUFFER), Buf::new(gl::DRAW_INDIRECT_BUFFER)));
+      draw_buf.data(gl::STREAM_DRAW, &draws);
+      draw_buf.bind_base(DRAW_BINDING);
+      command_buf.data(gl::STREAM_DRAW, &commands);
+      command_buf.bind();
+      for ((format, tex), start, count) in ranges {
+        pool::with(format, |it| it.vao.bind());
+        match tex.and_then(|it| textures.get(&it)) {
+          Some(tex) => {
+            tex.bind(gl::TEXTURE3);
+            shader.set_1i("u_index_tex", 3);
+            shader.set_1i("u_indexed", 1);
+          }
+          None => shader.set_1i("u_indexed", 0)
+        }
+
+        gl_multi_draw_elements_indirect(gl::TRIANGLES, start * size_of::<Command>(), count as i32);
+        stats.draw_calls += 1;
+      }
+    }
+
+    self.clear();
+  }
+
+  // empties every batch and forgets models that were dropped, their meshes have already
+  // given their pool ranges back
+  fn clear(&mut self) {
+    self.models.retain(|_, it| it.model.strong_count() > 0);
+    for it in self.models.values_mut() {
+      for level in &mut it.levels {
+        level.clear();
+      }
+    }
+  }
+}
+
+impl Default for Batches {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dropped_models_are_evicted() {
+    let instance = Instance { model: Mat4::IDENTITY.to_cols_array(), tint: -1, id: 0 };
+    let (kept, dropped) = (Rc::new(Model::from_meshes(Vec::new())), Rc::new(Model::from_meshes(Vec::new())));
+    let mut batches = Batches::new();
+    batches.add(&kept, 0, instance);
+    batches.add(&dropped, 1, instance);
+    drop(dropped);
+
+    batches.clear();
+    assert_eq!(batches.models.len(), 1);
+    let batch = &batches.models[&Rc::as_ptr(&kept)];
+    assert!(batch.levels.iter().all(Vec::is_empty));
+  }
+}
@@ -0,0 +1,129 @@
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+  pub min: Vec3,
+  pub max: Vec3,
+}
+
+impl Aabb {
+  pub const EMPTY: Aabb = Aabb { min: Vec3::splat(f32::MAX), max: Vec3::splat(f32::MIN) };
+
+  pub fn new(min: Vec3, max: Vec3) -> Aabb {
+    Aabb { min, max }
+  }
+
+  pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Aabb {
+    let mut res = Self::EMPTY;
+    for it in points {
+      res.min = res.min.min(it);
+      res.max = res.max.max(it);
+    }
+
+    res
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.min.cmpgt(self.max).any()
+  }
+
+  pub fn union(&self, other: &Aabb) -> Aabb {
+    Aabb { min: self.min.min(other.min), max: self.max.max(other.max) }
+  }
+
+  pub fn center(&self) -> Vec3 {
+    (self.min + self.max) * 0.5
+  }
+
+  pub fn extents(&self) -> Vec3 {
+    (self.max - self.min) * 0.5
+  }
+
+  pub fn contains(&self, point: Vec3) -> bool {
+    point.cmpge(self.min).all() && point.cmple(self.max).all()
+  }
+
+  // aabb around the transformed box, see Arvo's "transforming axis-aligned bounding boxes"
+  pub fn transform(&self, mat: &Mat4) -> Aabb {
+    if self.is_empty() {
+      return *self;
+    }
+
+    let center = mat.transform_point3(self.center());
+    let extents = self.extents();
+    let radius = Vec3::new(
+      mat.x_axis.x.abs() * extents.x + mat.y_axis.x.abs() * extents.y + mat.z_axis.x.abs() * extents.z,
+      mat.x_axis.y.abs() * extents.x + mat.y_axis.y.abs() * extents.y + mat.z_axis.y.abs() * extents.z,
+      mat.x_axis.z.abs() * extents.x + mat.y_axis.z.abs() * extents.y + mat.z_axis.z.abs() * extents.z,
+    );
+
+    Aabb { min: center - radius, max: center + radius }
+  }
+
+  pub fn sphere(&self) -> Sphere {
+    Sphere { center: self.center(), radius: self.extents().length() }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+  pub center: Vec3,
+  pub radius: f32,
+}
+
+impl Sphere {
+  pub fn transform(&self, mat: &Mat4) -> Sphere {
+    let scale = mat.x_axis.truncate().length().max(mat.y_axis.truncate().length()).max(mat.z_axis.truncate().length());
+    Sphere { center: mat.transform_point3(self.center), radius: self.radius * scale }
+  }
+}
+
+// planes point inwards, xyz is the normal and w the distance
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+  pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+  // gribb & hartmann, from the rows of proj * look with depth in 0..1
+  pub fn from_mat(mat: &Mat4) -> Frustum {
+    let rows = [mat.row(0), mat.row(1), mat.row(2), mat.row(3)];
+    let planes = [
+      rows[3] + rows[0],
+      rows[3] - rows[0],
+      rows[3] + rows[1],
+      rows[3] - rows[1],
+      rows[2],
+      rows[3] - rows[2],
+    ].map(|it| {
+      let len = it.xyz().length();
+      // an infinite far plane has no normal, keep it from culling anything
+      if len < 1e-6 { Vec4::W } else { it / len }
+    });
+
+    Frustum { planes }
+  }
+
+  pub fn contains_sphere(&self, sphere: &Sphere) -> bool {
+    self.planes.iter().all(|it| it.xyz().dot(sphere.center) + it.w >= -sphere.radius)
+  }
+
+  pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+    self.planes.iter().all(|it| {
+      // the corner furthest along the plane normal
+      let p = Vec3::select(it.xyz().cmpge(Vec3::ZERO), aabb.max, aabb.min);
+      it.xyz().dot(p) + it.w >= 0.
+    })
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+  pub cells_culled: u32,
+  pub objects_drawn: u32,
+  pub objects_culled: u32,
+  pub meshes_drawn: u32,
+  pub meshes_culled: u32,
+  pub triangles_drawn: u32,
+  pub draw_calls: u32,
+}
@@ -0,0 +1,399 @@
+use std::f32::consts::{PI, TAU};
+use glam::{IVec4, Mat4, Vec2, Vec3, Vec4};
+use crate::hana::model::{Mesh, Model, Vertex};
+use crate::hana::transform::norm_mat;
+
+// procedural geometry in the same Vertex layout the importer produces. y is up, everything is
+// centered on the origin and wound counter clockwise seen from outside. faces take the tint set
+// when they're added, -1 leaves them to the shader's tint uniform
+pub struct MeshBuilder {
+  pub vertices: Vec<Vertex>,
+  pub indices: Vec<u32>,
+  tint: i32
+}
+
+impl MeshBuilder {
+  pub fn new() -> MeshBuilder {
+    MeshBuilder { vertices: Vec::new(), indices: Vec::new(), tint: -1 }
+  }
+
+  pub fn tint(&mut self, tint: i32) -> &mut Self {
+    self.tint = tint;
+    self
+  }
+
+  pub fn vertex(&mut self, pos: Vec3, norm: Vec3, tex: Vec2) -> u32 {
+    self.vertices.push(Vertex { pos, norm, tint: self.tint, tex, bones: IVec4::ZERO, weights: Vec4::ZERO });
+    self.vertices.len() as u32 - 1
+  }
+
+  pub fn tri(&mut self, a: u32, b: u32, c: u32) -> &mut Self {
+    self.indices.extend_from_slice(&[a, b, c]);
+    self
+  }
+
+  pub fn quad(&mut self, a: u32, b: u32, c: u32, d: u32) -> &mut Self {
+    self.tri(a, b, c).tri(a, c, d)
+  }
+
+  // a subdivided rectangle facing `norm`, u x v has to point along it
+  fn grid(&mut self, center: Vec3, u: Vec3, v: Vec3, subdivisions: u32) {
+    let n = subdivisions.max(1);
+    let norm = u.cross(v).normalize();
+    let first = self.vertices.len() as u32;
+    for j in 0..=n {
+      for i in 0..=n {
+        let tex = Vec2::new(i as f32 / n as f32, j as f32 / n as f32);
+        self.vertex(center + u * (tex.x * 2. - 1.) + v * (tex.y * 2. - 1.), norm, tex);
+      }
+    }
+
+    for j in 0..n {
+      for i in 0..n {
+        let a = first + j * (n + 1) + i;
+        self.quad(a, a + 1, a + n + 2, a + n + 1);
+      }
+    }
+  }
+
+  pub fn cube(&mut self, size: Vec3) -> &mut Self {
+    let half = size * 0.5;
+    for norm in [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z] {
+      let v = if norm.y.abs() > 0.5 { Vec3::Z } else { Vec3::Y };
+      let u = v.cross(norm);
+      self.grid(norm * half, u * half, v * half, 1);
+    }
+
+    self
+  }
+
+  // in xz, facing up
+  pub fn plane(&mut self, size: Vec2, subdivisions: u32) -> &mut Self {
+    self.grid(Vec3::ZERO, Vec3::X * size.x * 0.5, Vec3::NEG_Z * size.y * 0.5, subdivisions);
+    self
+  }
+
+  // spins a profile of (position, normal) pairs in the xy plane around y. the first ring
+  // repeats at the end so uvs wrap cleanly
+  fn revolve(&mut self, profile: &[(Vec2, Vec2)], segments: u32) {
+    let segments = segments.max(3);
+    let first = self.vertices.len() as u32;
+    for (i, (pos, norm)) in profile.iter().enumerate() {
+      for j in 0..=segments {
+        let angle = j as f32 / segments as f32 * TAU;
+        let (sin, cos) = angle.sin_cos();
+        self.vertex(
+          Vec3::new(pos.x * cos, pos.y, pos.x * sin),
+          Vec3::new(norm.x * cos, norm.y, norm.x * sin).normalize_or_zero(),
+          Vec2::new(j as f32 / segments as f32, i as f32 / (profile.len() - 1).max(1) as f32)
+        );
+      }
+    }
+
+    let ring = segments + 1;
+    for i in 0..profile.len().saturating_sub(1) as u32 {
+      for j in 0..segments {
+        let a = first + i * ring + j;
+        let b = a + ring;
+        self.tri(a, b, a + 1).tri(a + 1, b, b + 1);
+      }
+    }
+  }
+
+  // a flat disc at height y, facing up or down
+  fn cap(&mut self, radius: f32, y: f32, up: bool, segments: u32) {
+    let segments = segments.max(3);
+    let norm = if up { Vec3::Y } else { Vec3::NEG_Y };
+    let center = self.vertex(Vec3::new(0., y, 0.), norm, Vec2::splat(0.5));
+    for j in 0..=segments {
+      let (sin, cos) = (j as f32 / segments as f32 * TAU).sin_cos();
+      self.vertex(Vec3::new(radius * cos, y, radius * sin), norm, Vec2::new(cos, sin) * 0.5 + 0.5);
+    }
+
+    for j in 0..segments {
+      let (a, b) = (center + 1 + j, center + 2 + j);
+      if up {
+        self.tri(center, b, a);
+      } else {
+        self.tri(center, a, b);
+      }
+    }
+  }
+
+  // points go around x on the left of the profile, so the profile is (radius, height).
+  // normals come from the neighbouring points, repeat a point for a hard edge
+  pub fn lathe(&mut self, profile: &[Vec2], segments: u32) -> &mut Self {
+    let profile = profile.iter().enumerate().map(|(i, it)| {
+      let prev = profile[i.saturating_sub(1)];
+      let next = profile[(i + 1).min(profile.len() - 1)];
+      let tangent = next - prev;
+      (*it, Vec2::new(tangent.y, -tangent.x).normalize_or_zero())
+    }).collect::<Vec<_>>();
+
+    self.revolve(&profile, segments);
+    self
+  }
+
+  pub fn sphere(&mut self, radius: f32, segments: u32, rings: u32) -> &mut Self {
+    let rings = rings.max(2);
+    let profile = (0..=rings).map(|i| {
+      let (sin, cos) = (i as f32 / rings as f32 * PI).sin_cos();
+      let norm = Vec2::new(sin, -cos);
+      (norm * radius, norm)
+    }).collect::<Vec<_>>();
+
+    self.revolve(&profile, segments);
+    self
+  }
+
+  pub fn cylinder(&mut self, radius: f32, height: f32, segments: u32) -> &mut Self {
+    let half = height * 0.5;
+    self.revolve(&[(Vec2::new(radius, -half), Vec2::X), (Vec2::new(radius, half), Vec2::X)], segments);
+    self.cap(radius, half, true, segments);
+    self.cap(radius, -half, false, segments);
+    self
+  }
+
+  // `height` is the straight part, the whole thing is height + radius * 2 tall
+  pub fn capsule(&mut self, radius: f32, height: f32, segments: u32, rings: u32) -> &mut Self {
+    // rings per hemisphere, the equator is there twice to make the straight part
+    let half_rings = (rings / 2).max(1);
+    let half = height * 0.5;
+    let mut profile = Vec::new();
+    for (offset, from) in [(-half, 0), (half, half_rings)] {
+      for i in from..=from + half_rings {
+        let (sin, cos) = (i as f32 / (half_rings * 2) as f32 * PI).sin_cos();
+        let norm = Vec2::new(sin, -cos);
+        profile.push((norm * radius + Vec2::new(0., offset), norm));
+      }
+    }
+
+    self.revolve(&profile, segments);
+    self
+  }
+
+  // `radius` to the middle of the tube, lying in xz
+  pub fn torus(&mut self, radius: f32, tube: f32, segments: u32, sides: u32) -> &mut Self {
+    let sides = sides.max(3);
+    let profile = (0..=sides).map(|i| {
+      let (sin, cos) = (i as f32 / sides as f32 * TAU).sin_cos();
+      let norm = Vec2::new(cos, sin);
+      (Vec2::new(radius, 0.) + norm * tube, norm)
+    }).collect::<Vec<_>>();
+
+    self.revolve(&profile, segments);
+    self
+  }
+
+  // a closed polygon in xy pushed out along z, concave is fine but it can't cross itself
+  pub fn extrude(&mut self, profile: &[Vec2], depth: f32) -> &mut Self {
+    if profile.len() < 3 {
+      return self;
+    }
+
+    let mut profile = profile.to_vec();
+    if signed_area(&profile) < 0. {
+      profile.reverse();
+    }
+
+    let half = depth * 0.5;
+    for i in 0..profile.len() {
+      let (a, b) = (profile[i], profile[(i + 1) % profile.len()]);
+      let edge = b - a;
+      let norm = Vec3::new(edge.y, -edge.x, 0.).normalize_or_zero();
+      let len = edge.length();
+      let first = self.vertex(a.extend(-half), norm, Vec2::new(0., 0.));
+      self.vertex(b.extend(-half), norm, Vec2::new(len, 0.));
+      self.vertex(b.extend(half), norm, Vec2::new(len, depth));
+      self.vertex(a.extend(half), norm, Vec2::new(0., depth));
+      self.quad(first, first + 1, first + 2, first + 3);
+    }
+
+    let tris = triangulate(&profile);
+    for (z, norm) in [(half, Vec3::Z), (-half, Vec3::NEG_Z)] {
+      let first = self.vertices.len() as u32;
+      for it in &profile {
+        self.vertex(it.extend(z), norm, *it);
+      }
+
+      for [a, b, c] in &tris {
+        if z > 0. {
+          self.tri(first + a, first + b, first + c);
+        } else {
+          self.tri(first + a, first + c, first + b);
+        }
+      }
+    }
+
+    self
+  }
+
+  pub fn merge(&mut self, other: &MeshBuilder) -> &mut Self {
+    self.merge_transformed(other, &Mat4::IDENTITY)
+  }
+
+  pub fn merge_transformed(&mut self, other: &MeshBuilder, transform: &Mat4) -> &mut Self {
+    let first = self.vertices.len() as u32;
+    let norm = norm_mat(transform);
+    self.vertices.extend(other.vertices.iter().map(|it| {
+      let mut res = it.clone();
+      res.pos = transform.transform_point3(it.pos);
+      res.norm = (norm * it.norm).normalize_or_zero();
+      res
+    }));
+
+    self.indices.extend(other.indices.iter().map(|it| it + first));
+    self
+  }
+
+  // gives every triangle its own vertices so each can be tinted on its own, `tint` gets the
+  // triangle's index and face normal
+  pub fn tint_faces(&mut self, tint: impl Fn(usize, Vec3) -> i32) -> &mut Self {
+    let mut vertices = Vec::with_capacity(self.indices.len());
+    for (i, tri) in self.indices.chunks_exact(3).enumerate() {
+      let [a, b, c] = [0, 1, 2].map(|it| self.vertices[tri[it] as usize].clone());
+      let norm = (b.pos - a.pos).cross(c.pos - a.pos).normalize_or_zero();
+      let tint = tint(i, norm);
+      for mut it in [a, b, c] {
+        it.tint = tint;
+        vertices.push(it);
+      }
+    }
+
+    self.indices = (0..vertices.len() as u32).collect();
+    self.vertices = vertices;
+    self
+  }
+
+  pub fn build(&self) -> Mesh {
+    Mesh::optimized(self.vertices.clone(), self.indices.clone(), true)
+  }
+
+  pub fn model(&self) -> Model {
+    Model::from_meshes(vec![self.build()])
+  }
+}
+
+impl Default for MeshBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn signed_area(poly: &[Vec2]) -> f32 {
+  (0..poly.len()).map(|i| poly[i].perp_dot(poly[(i + 1) % poly.len()])).sum::<f32>() * 0.5
+}
+
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+  (b - a).perp_dot(p - a) >= 0. && (c - b).perp_dot(p - b) >= 0. && (a - c).perp_dot(p - c) >= 0.
+}
+
+// ear clipping on a counter clockwise polygon
+fn triangulate(poly: &[Vec2]) -> Vec<[u32; 3]> {
+  let mut left = (0..poly.len() as u32).collect::<Vec<_>>();
+  let mut res = Vec::new();
+  while left.len() > 3 {
+    let n = left.len();
+    let ear = (0..n).find(|i| {
+      let [a, b, c] = [left[(i + n - 1) % n], left[*i], left[(i + 1) % n]].map(|it| poly[it as usize]);
+      (b - a).perp_dot(c - b) > 0. && left.iter().all(|it| {
+        let p = poly[*it as usize];
+        p == a || p == b || p == c || !in_triangle(p, a, b, c)
+      })
+    });
+
+    // only self intersecting input gets here, fan whatever is left
+    let Some(i) = ear else {
+      break;
+    };
+
+    res.push([left[(i + n - 1) % n], left[i], left[(i + 1) % n]]);
+    left.remove(i);
+  }
+
+  for i in 1..left.len().saturating_sub(1) {
+    res.push([left[0], left[i], left[i + 1]]);
+  }
+
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use glam::{Mat4, Vec2, Vec3};
+  use super::{signed_area, triangulate, MeshBuilder};
+
+  // every triangle with an area faces the same way as its vertex normals
+  fn outward(it: &MeshBuilder) -> bool {
+    it.indices.chunks_exact(3).all(|tri| {
+      let [a, b, c] = [0, 1, 2].map(|i| &it.vertices[tri[i] as usize]);
+      let face = (b.pos - a.pos).cross(c.pos - a.pos);
+      face.length() < 1e-6 || face.dot(a.norm + b.norm + c.norm) > 0.
+    })
+  }
+
+  fn counts(it: &MeshBuilder) -> (usize, usize) {
+    (it.vertices.len(), it.indices.len())
+  }
+
+  #[test]
+  fn vertex_and_index_counts() {
+    assert_eq!(counts(MeshBuilder::new().cube(Vec3::ONE)), (24, 36));
+    assert_eq!(counts(MeshBuilder::new().plane(Vec2::ONE, 4)), (25, 96));
+    // rings + 1 rows of segments + 1 vertices
+    assert_eq!(counts(MeshBuilder::new().sphere(1., 8, 4)), (45, 192));
+    // the side, then two caps of a center and segments + 1 vertices
+    assert_eq!(counts(MeshBuilder::new().cylinder(1., 2., 6)), (14 + 8 * 2, 36 + 18 * 2));
+    // a quad per edge and the same triangulation on both ends
+    assert_eq!(counts(MeshBuilder::new().extrude(&[Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y], 1.)), (16 + 8, 24 + 12));
+  }
+
+  #[test]
+  fn wound_outwards() {
+    let star = (0..10).map(|i| {
+      let r = if i % 2 == 0 { 1. } else { 0.4 };
+      Vec2::from_angle(i as f32 * std::f32::consts::TAU / 10.) * r
+    }).collect::<Vec<_>>();
+
+    for it in [
+      MeshBuilder::new().cube(Vec3::new(1., 2., 3.)),
+      MeshBuilder::new().plane(Vec2::ONE, 3),
+      MeshBuilder::new().sphere(1., 12, 6),
+      MeshBuilder::new().cylinder(0.5, 2., 9),
+      MeshBuilder::new().capsule(0.5, 1., 10, 6),
+      MeshBuilder::new().torus(1., 0.25, 12, 6),
+      MeshBuilder::new().lathe(&[Vec2::new(0.2, 0.), Vec2::new(0.5, 0.5), Vec2::new(0.3, 1.)], 8),
+      MeshBuilder::new().extrude(&star, 0.5),
+      // clockwise input gets turned around
+      MeshBuilder::new().extrude(&star.iter().rev().copied().collect::<Vec<_>>(), 0.5)
+    ] {
+      assert!(outward(it));
+    }
+  }
+
+  #[test]
+  fn ear_clipping() {
+    // an L, concave at (1, 1)
+    let l = [Vec2::ZERO, Vec2::new(2., 0.), Vec2::new(2., 1.), Vec2::new(1., 1.), Vec2::new(1., 2.), Vec2::new(0., 2.)];
+    assert_eq!(signed_area(&l), 3.);
+    let tris = triangulate(&l);
+    assert_eq!(tris.len(), l.len() - 2);
+    let area = tris.iter().map(|it| signed_area(&it.map(|i| l[i as usize]))).sum::<f32>();
+    assert!((area - 3.).abs() < 1e-6);
+  }
+
+  #[test]
+  fn merging_and_face_tints() {
+    let mut cube = MeshBuilder::new();
+    cube.tint(2).cube(Vec3::ONE);
+    let mut both = MeshBuilder::new();
+    both.merge(&cube).merge_transformed(&cube, &Mat4::from_translation(Vec3::X * 3.));
+    assert_eq!(counts(&both), (48, 72));
+    assert_eq!(both.indices[36], 24);
+    assert!(both.vertices[24..].iter().all(|it| it.pos.x >= 2.5 && it.tint == 2));
+
+    both.tint_faces(|i, norm| if norm.y > 0.5 { 1 } else { i as i32 % 2 });
+    assert_eq!(counts(&both), (72, 72));
+    assert!(both.vertices.iter().filter(|it| it.norm.y > 0.5).all(|it| it.tint == 1));
+  }
+}
@@ -0,0 +1,576 @@
+use std::fs;
+use std::path::Path;
+use std::iter::zip;
+use std::mem::{size_of, size_of_val};
+use glam::{Mat4, Quat, Vec3};
+use crate::hana::anim::{Bone, Channel, Clip};
+use crate::hana::bounds::Aabb;
+use crate::hana::glu::{Tex, TexSpec};
+use crate::hana::lod::Lod;
+use crate::hana::model::{ImportOptions, Material, Mesh, Model, Morph, Node, Normals, Vertex};
+use crate::hana::palette::Palette;
+
+// .hmesh layout, all little endian:
+//   magic, version, stamp (source + import options), checksum of the payload, payload
+// the payload starts with the files the source pulls in (materials, textures) and their hashes.
+// bump VERSION whenever the payload or Vertex changes
+pub const VERSION: u32 = 7;
+const MAGIC: &[u8; 4] = b"HMSH";
+const HEADER_LEN: usize = 4 + 4 + 8 + 8;
+
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+  bytes.iter().fold(hash, |hash, it| (hash ^ *it as u64).wrapping_mul(0x100000001b3))
+}
+
+pub const FNV_BASIS: u64 = 0xcbf29ce484222325;
+
+pub fn path(src: &str) -> String {
+  src.to_string() + ".hmesh"
+}
+
+// changes whenever the source file or anything that affects the import does
+pub fn stamp(src: &str, palette: &Palette, options: &ImportOptions) -> Result<u64, String> {
+  let bytes = fs::read(src).map_err(|e| src.to_string() + ": " + &e.to_string())?;
+  let mut hash = fnv1a(FNV_BASIS, &bytes);
+  for it in &palette.0 {
+    hash = fnv1a(hash, as_bytes(&it.to_array()));
+  }
+
+  let mut entries = options.tints.iter().collect::<Vec<_>>();
+  entries.sort();
+  for (name, tint) in entries {
+    hash = fnv1a(hash, name.as_bytes());
+    hash = fnv1a(hash, &tint.to_le_bytes());
+  }
+
+  // packing happens after loading, so it doesn't need a separate cache
+  let normals = match options.normals {
+    Normals::Imported => [0., 0.],
+    Normals::Flat => [1., 0.],
+    Normals::Smooth(angle) => [2., angle]
+  };
+
+  hash = fnv1a(hash, as_bytes(&normals));
+  hash = fnv1a(hash, &[options.outline_normals as u8, options.lods as u8]);
+  for (name, path) in &options.morph_targets {
+    hash = fnv1a(hash, name.as_bytes());
+    hash = fnv1a(hash, &fs::read(path).map_err(|e| path.to_string() + ": " + &e.to_string())?);
+  }
+
+  Ok(hash)
+}
+
+fn hash_file(path: &str) -> Result<u64, String> {
+  Ok(fnv1a(FNV_BASIS, &fs::read(path).map_err(|e| path.to_string() + ": " + &e.to_string())?))
+}
+
+// what else went into the import, so editing a .mtl or a texture rebuilds the cache too
+fn dependencies(src: &str, model: &Model) -> Vec<String> {
+  let mut res = Vec::new();
+  // assimp doesn't say which material libraries it read, so they're picked out of the obj
+  if src.ends_with(".obj") {
+    let dir = Path::new(src).parent().unwrap_or(Path::new(""));
+    let text = fs::read_to_string(src).unwrap_or_default();
+    for line in text.lines() {
+      if let Some(lib) = line.trim().strip_prefix("mtllib ") {
+        res.push(dir.join(lib.trim()).to_string_lossy().to_string());
+      }
+    }
+  }
+
+  res.extend(model.materials.iter().filter_map(|it| it.tex_path.clone()));
+  res.sort();
+  res.dedup();
+  res
+}
+
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+  unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+  fn u8(&mut self, val: u8) {
+    self.0.push(val)
+  }
+
+  fn u32(&mut self, val: u32) {
+    self.0.extend_from_slice(&val.to_le_bytes())
+  }
+
+  fn i32(&mut self, val: i32) {
+    self.0.extend_from_slice(&val.to_le_bytes())
+  }
+
+  fn f32(&mut self, val: f32) {
+    self.0.extend_from_slice(&val.to_le_bytes())
+  }
+
+  fn f32s(&mut self, val: &[f32]) {
+    for it in val {
+      self.f32(*it)
+    }
+  }
+
+  fn str(&mut self, val: &str) {
+    self.u32(val.len() as u32);
+    self.0.extend_from_slice(val.as_bytes())
+  }
+
+  fn keys<T>(&mut self, keys: &[(f32, T)], put: impl Fn(&mut Self, &T)) {
+    self.u32(keys.len() as u32);
+    for (time, it) in keys {
+      self.f32(*time);
+      put(self, it);
+    }
+  }
+}
+
+struct Reader<'a> {
+  buf: &'a [u8],
+  pos: usize
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+    if self.buf.len() - self.pos < len {
+      return Err("unexpected end of file".into());
+    }
+
+    self.pos += len;
+    Ok(&self.buf[self.pos - len..self.pos])
+  }
+
+  fn u8(&mut self) -> Result<u8, String> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn len(&mut self) -> Result<usize, String> {
+    Ok(self.u32()? as usize)
+  }
+
+  fn i32(&mut self) -> Result<i32, String> {
+    Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn f32(&mut self) -> Result<f32, String> {
+    Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn f32s<const N: usize>(&mut self) -> Result<[f32; N], String> {
+    let mut res = [0.; N];
+    for it in &mut res {
+      *it = self.f32()?;
+    }
+
+    Ok(res)
+  }
+
+  fn vec3(&mut self) -> Result<Vec3, String> {
+    Ok(Vec3::from_array(self.f32s()?))
+  }
+
+  fn mat4(&mut self) -> Result<Mat4, String> {
+    Ok(Mat4::from_cols_array(&self.f32s()?))
+  }
+
+  fn str(&mut self) -> Result<String, String> {
+    let len = self.len()?;
+    String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+  }
+
+  fn keys<T>(&mut self, get: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<(f32, T)>, String> {
+    (0..self.len()?).map(|_| Ok((self.f32()?, get(self)?))).collect()
+  }
+}
+
+// next to `src`, see path
+pub fn write(model: &Model, src: &str, stamp: u64) -> Result<(), String> {
+  let mut w = Writer(Vec::new());
+
+  let deps = dependencies(src, model);
+  w.u32(deps.len() as u32);
+  for it in &deps {
+    w.str(it);
+    w.0.extend_from_slice(&hash_file(it)?.to_le_bytes());
+  }
+
+  w.u32(model.materials.len() as u32);
+  for it in &model.materials {
+    w.str(&it.name);
+    w.str(it.tex_path.as_deref().unwrap_or(""));
+  }
+
+  w.u32(model.meshes.len() as u32);
+  for it in &model.meshes {
+    w.u32(it.material as u32);
+    w.u8(it.skinned as u8);
+    w.f32s(&it.bounds.min.to_array());
+    w.f32s(&it.bounds.max.to_array());
+    w.u32(it.vertices.len() as u32);
+    w.0.extend_from_slice(as_bytes(&it.vertices));
+    w.u32(it.indices.len() as u32);
+    for index in &it.indices {
+      w.u32(*index);
+    }
+
+    w.u32(it.lods.len() as u32);
+    for lod in &it.lods {
+      w.u32(lod.start);
+      w.u32(lod.count);
+    }
+
+    w.u32(it.outline.len() as u32);
+    for norm in &it.outline {
+      w.f32s(&norm.to_array());
+    }
+
+    w.u32(it.morphs.len() as u32);
+    for morph in &it.morphs {
+      w.str(&morph.name);
+      for (pos, norm) in morph.pos.iter().zip(&morph.norm) {
+        w.f32s(&pos.to_array());
+        w.f32s(&norm.to_array());
+      }
+    }
+  }
+
+  w.u32(model.nodes.len() as u32);
+  for it in &model.nodes {
+    w.str(&it.name);
+    w.f32s(&it.transform.to_cols_array());
+    w.i32(it.parent.map_or(-1, |it| it as i32));
+    w.u32(it.meshes.len() as u32);
+    for mesh in &it.meshes {
+      w.u32(*mesh as u32);
+    }
+  }
+
+  w.u32(model.bones.len() as u32);
+  for it in &model.bones {
+    w.str(&it.name);
+    w.u32(it.node as u32);
+    w.f32s(&it.offset.to_cols_array());
+  }
+
+  w.u32(model.clips.len() as u32);
+  for it in &model.clips {
+    w.str(&it.name);
+    w.f32(it.duration);
+    w.u32(it.channels.len() as u32);
+    for channel in &it.channels {
+      w.u32(channel.node as u32);
+      w.keys(&channel.pos, |w, it| w.f32s(&it.to_array()));
+      w.keys(&channel.rot, |w, it| w.f32s(&it.to_array()));
+      w.keys(&channel.scale, |w, it| w.f32s(&it.to_array()));
+    }
+  }
+
+  let dst = path(src);
+  fs::write(&dst, with_header(stamp, &w.0)).map_err(|e| dst + ": " + &e.to_string())
+}
+
+fn with_header(stamp: u64, payload: &[u8]) -> Vec<u8> {
+  let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+  out.extend_from_slice(MAGIC);
+  out.extend_from_slice(&VERSION.to_le_bytes());
+  out.extend_from_slice(&stamp.to_le_bytes());
+  out.extend_from_slice(&fnv1a(FNV_BASIS, payload).to_le_bytes());
+  out.extend_from_slice(payload);
+  out
+}
+
+// fails on anything stale or corrupt, callers fall back to importing the source
+pub fn read(src: &str, stamp: u64, palette: &Palette) -> Result<Model, String> {
+  let buf = fs::read(src).map_err(|e| src.to_string() + ": " + &e.to_string())?;
+  if buf.len() < HEADER_LEN || &buf[0..4] != MAGIC {
+    return Err("not a hmesh file".into());
+  }
+
+  let mut r = Reader { buf: &buf, pos: 4 };
+  let version = r.u32()?;
+  if version != VERSION {
+    return Err(format!("version {} != {}", version, VERSION));
+  }
+
+  if u64::from_le_bytes(r.take(8)?.try_into().unwrap()) != stamp {
+    return Err("source changed".into());
+  }
+
+  let checksum = u64::from_le_bytes(r.take(8)?.try_into().unwrap());
+  if fnv1a(FNV_BASIS, &buf[HEADER_LEN..]) != checksum {
+    return Err("checksum mismatch".into());
+  }
+
+  for _ in 0..r.len()? {
+    let dep = r.str()?;
+    if hash_file(&dep).ok() != Some(u64::from_le_bytes(r.take(8)?.try_into().unwrap())) {
+      return Err(dep + " changed");
+    }
+  }
+
+  let mut materials = Vec::new();
+  for _ in 0..r.len()? {
+    let name = r.str()?;
+    let tex_path = Some(r.str()?).filter(|it| !it.is_empty());
+    let tex = match &tex_path {
+      Some(path) => Some(Tex::new(&TexSpec::palette_indexed(path, palette)?)),
+      None => None
+    };
+
+    materials.push(Material { name, tex_path, tex });
+  }
+
+  let mut meshes = Vec::new();
+  for _ in 0..r.len()? {
+    let material = r.len()?;
+    let skinned = r.u8()? != 0;
+    let bounds = Aabb::new(r.vec3()?, r.vec3()?);
+
+    let n_vertices = r.len()?;
+    let bytes = r.take(n_vertices.checked_mul(size_of::<Vertex>()).ok_or("vertex count out of range")?)?;
+    let mut vertices = vec![Vertex::empty(); n_vertices];
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), vertices.as_mut_ptr() as *mut u8, bytes.len()) }
+
+    let indices = (0..r.len()?).map(|_| r.u32()).collect::<Result<Vec<_>, String>>()?;
+    if indices.iter().any(|it| *it as usize >= n_vertices) {
+      return Err("index out of range".into());
+    }
+
+    let lods = (0..r.len()?).map(|_| Ok(Lod { start: r.u32()?, count: r.u32()? })).collect::<Result<Vec<_>, String>>()?;
+    // start + count can overflow on a corrupt file
+    if lods.is_empty() || lods.iter().any(|it| it.start.checked_add(it.count).map_or(true, |end| end as usize > indices.len())) {
+      return Err("lod out of range".into());
+    }
+
+    let outline = (0..r.len()?).map(|_| r.vec3()).collect::<Result<Vec<_>, String>>()?;
+    if !outline.is_empty() && outline.len() != n_vertices {
+      return Err("outline normals don't match the vertices".into());
+    }
+
+    let mut mesh = Mesh::with_bounds(vertices, indices, bounds);
+    mesh.material = material;
+    mesh.skinned = skinned;
+    mesh.lods = lods;
+    mesh.set_outline(outline);
+
+    let mut morphs = Vec::new();
+    for _ in 0..r.len()? {
+      let name = r.str()?;
+      let (mut pos, mut norm) = (Vec::with_capacity(n_vertices), Vec::with_capacity(n_vertices));
+      for _ in 0..n_vertices {
+        pos.push(r.vec3()?);
+        norm.push(r.vec3()?);
+      }
+
+      morphs.push(Morph { name, pos, norm, slot: 0 });
+    }
+
+    mesh.set_morphs(morphs);
+    meshes.push(mesh);
+  }
+
+  let mut nodes = Vec::new();
+  for _ in 0..r.len()? {
+    nodes.push(Node {
+      name: r.str()?,
+      transform: r.mat4()?,
+      parent: usize::try_from(r.i32()?).ok(),
+      meshes: (0..r.len()?).map(|_| r.len()).collect::<Result<Vec<_>, String>>()?
+    });
+  }
+
+  let mut bones = Vec::new();
+  for _ in 0..r.len()? {
+    bones.push(Bone { name: r.str()?, node: r.len()?, offset: r.mat4()? });
+  }
+
+  let mut clips = Vec::new();
+  for _ in 0..r.len()? {
+    let name = r.str()?;
+    let duration = r.f32()?;
+    let mut channels = Vec::new();
+    for _ in 0..r.len()? {
+      channels.push(Channel {
+        node: r.len()?,
+        pos: r.keys(|r| r.vec3())?,
+        rot: r.keys(|r| Ok(Quat::from_array(r.f32s()?)))?,
+        scale: r.keys(|r| r.vec3())?
+      });
+    }
+
+    clips.push(Clip { name, duration, channels });
+  }
+
+  if nodes.iter().any(|it| it.meshes.iter().any(|mesh| *mesh >= meshes.len())) {
+    return Err("node references a missing mesh".into());
+  }
+
+  // parents always come before their children
+  if nodes.iter().enumerate().any(|(i, it)| it.parent.is_some_and(|parent| parent >= i)) {
+    return Err("node parent out of order".into());
+  }
+
+  if bones.iter().any(|it| it.node >= nodes.len()) {
+    return Err("bone references a missing node".into());
+  }
+
+  if clips.iter().any(|it| it.channels.iter().any(|channel| channel.node >= nodes.len())) {
+    return Err("channel references a missing node".into());
+  }
+
+  let skinned_out_of_range = meshes.iter().filter(|it| it.skinned).any(|it| it.vertices.iter().any(|vertex| {
+    // copied out since the vertex is packed
+    let (ids, weights) = ({ vertex.bones }.to_array(), { vertex.weights }.to_array());
+    zip(ids, weights).any(|(id, weight)| weight > 0. && (id < 0 || id as usize >= bones.len()))
+  }));
+
+  if skinned_out_of_range {
+    return Err("vertex references a missing bone".into());
+  }
+
+  Ok(Model::assemble(meshes, materials, nodes, bones, clips))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp(name: &str) -> String {
+    std::env::temp_dir().join(name).to_str().unwrap().to_string()
+  }
+
+  // reads `bytes` back from a file of its own
+  fn read_bytes(name: &str, bytes: &[u8], stamp: u64) -> Result<Model, String> {
+    let path = temp(name);
+    fs::write(&path, bytes).unwrap();
+    let res = read(&path, stamp, &Palette::new(&[]));
+    fs::remove_file(&path).unwrap();
+    res
+  }
+
+  // no meshes, those need a gl context
+  fn model() -> Model {
+    let nodes = vec![
+      Node { name: "root".into(), transform: Mat4::from_translation(Vec3::Y), parent: None, meshes: Vec::new() },
+      Node { name: "arm".into(), transform: Mat4::from_rotation_z(1.), parent: Some(0), meshes: Vec::new() }
+    ];
+
+    let bones = vec![Bone { name: "arm".into(), node: 1, offset: Mat4::from_scale(Vec3::splat(2.)) }];
+    let clips = vec![Clip {
+      name: "wave".into(),
+      duration: 2.,
+      channels: vec![Channel { node: 1, pos: vec![(0., Vec3::X)], rot: vec![(0., Quat::IDENTITY), (2., Quat::from_rotation_z(1.))], scale: Vec::new() }]
+    }];
+
+    let materials = vec![Material { name: "skin".into(), tex_path: None, tex: None }];
+    Model::assemble(Vec::new(), materials, nodes, bones, clips)
+  }
+
+  // one mesh of three empty vertices, up to where the mesh gets made
+  fn mesh_payload(indices: &[u32], lods: &[(u32, u32)]) -> Vec<u8> {
+    let mut w = Writer(Vec::new());
+    w.u32(0);
+    w.u32(0);
+    w.u32(1);
+    w.u32(0);
+    w.u8(0);
+    w.f32s(&[0.; 6]);
+    w.u32(3);
+    w.0.extend_from_slice(as_bytes(&vec![Vertex::empty(); 3]));
+    w.u32(indices.len() as u32);
+    for it in indices {
+      w.u32(*it);
+    }
+
+    w.u32(lods.len() as u32);
+    for (start, count) in lods {
+      w.u32(*start);
+      w.u32(*count);
+    }
+
+    w.0
+  }
+
+  #[test]
+  fn write_read_round_trip() {
+    let src = temp("hana_cache_test.glb");
+    write(&model(), &src, 42).unwrap();
+    let bytes = fs::read(path(&src)).unwrap();
+    fs::remove_file(path(&src)).unwrap();
+
+    let model = read_bytes("hana_cache_test_round_trip.hmesh", &bytes, 42).unwrap();
+    assert_eq!(model.materials.iter().map(|it| it.name.as_str()).collect::<Vec<_>>(), ["skin"]);
+    assert!(model.materials[0].tex_path.is_none());
+    assert_eq!(model.nodes.iter().map(|it| (it.name.as_str(), it.parent)).collect::<Vec<_>>(), [("root", None), ("arm", Some(0))]);
+    assert_eq!(model.nodes[1].transform, Mat4::from_rotation_z(1.));
+    assert_eq!((model.bones[0].name.as_str(), model.bones[0].node, model.bones[0].offset), ("arm", 1, Mat4::from_scale(Vec3::splat(2.))));
+
+    let clip = &model.clips[0];
+    assert_eq!((clip.name.as_str(), clip.duration, clip.channels.len()), ("wave", 2., 1));
+    assert_eq!(clip.channels[0].pos, [(0., Vec3::X)]);
+    assert_eq!(clip.channels[0].rot, [(0., Quat::IDENTITY), (2., Quat::from_rotation_z(1.))]);
+    assert!(clip.channels[0].scale.is_empty());
+
+    assert_eq!(read_bytes("hana_cache_test_stale.hmesh", &bytes, 43).err().as_deref(), Some("source changed"));
+  }
+
+  #[test]
+  fn rejects_a_bad_header() {
+    let good = with_header(1, &[0; 20]);
+    let mut magic = good.clone();
+    magic[0..4].copy_from_slice(b"HMSX");
+    assert_eq!(read_bytes("hana_cache_test_magic.hmesh", &magic, 1).err().as_deref(), Some("not a hmesh file"));
+
+    let mut version = good.clone();
+    version[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(read_bytes("hana_cache_test_version.hmesh", &version, 1).err(), Some(format!("version {} != {}", VERSION + 1, VERSION)));
+
+    let mut corrupt = good.clone();
+    corrupt[HEADER_LEN] = 1;
+    assert_eq!(read_bytes("hana_cache_test_corrupt.hmesh", &corrupt, 1).err().as_deref(), Some("checksum mismatch"));
+    assert_eq!(read_bytes("hana_cache_test_header.hmesh", &good[..HEADER_LEN - 1], 1).err().as_deref(), Some("not a hmesh file"));
+  }
+
+  #[test]
+  fn rejects_a_truncated_payload() {
+    let src = temp("hana_cache_test_truncated.glb");
+    write(&model(), &src, 1).unwrap();
+    let bytes = fs::read(path(&src)).unwrap();
+    fs::remove_file(path(&src)).unwrap();
+
+    // with the checksum redone, so it's the reader that runs out
+    let payload = &bytes[HEADER_LEN..];
+    let truncated = with_header(1, &payload[..payload.len() - 3]);
+    assert_eq!(read_bytes("hana_cache_test_truncated.hmesh", &truncated, 1).err().as_deref(), Some("unexpected end of file"));
+  }
+
+  #[test]
+  fn rejects_out_of_range_indices() {
+    let file = |indices: &[u32], lods: &[(u32, u32)]| with_header(1, &mesh_payload(indices, lods));
+    assert_eq!(read_bytes("hana_cache_test_index.hmesh", &file(&[0, 1, 3], &[(0, 3)]), 1).err().as_deref(), Some("index out of range"));
+    assert_eq!(read_bytes("hana_cache_test_lod.hmesh", &file(&[0, 1, 2], &[(1, 3)]), 1).err().as_deref(), Some("lod out of range"));
+    assert_eq!(read_bytes("hana_cache_test_overflow.hmesh", &file(&[0, 1, 2], &[(u32::MAX, 2)]), 1).err().as_deref(), Some("lod out of range"));
+    assert_eq!(read_bytes("hana_cache_test_no_lods.hmesh", &file(&[0, 1, 2], &[]), 1).err().as_deref(), Some("lod out of range"));
+
+    // no meshes at all, with a node pointing at one
+    let mut w = Writer(Vec::new());
+    w.u32(0);
+    w.u32(0);
+    w.u32(0);
+    w.u32(1);
+    w.str("root");
+    w.f32s(&Mat4::IDENTITY.to_cols_array());
+    w.i32(-1);
+    w.u32(1);
+    w.u32(0);
+    w.u32(0);
+    w.u32(0);
+    assert_eq!(read_bytes("hana_cache_test_node.hmesh", &with_header(1, &w.0), 1).err().as_deref(), Some("node references a missing mesh"));
+  }
+}
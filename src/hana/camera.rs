@@ -1,73 +1,229 @@
-use glam::{Mat4, Vec3};
-use glfw::Key;
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use crate::hana::anim::TICK_LENGTH;
+use crate::hana::bounds::{Frustum, Sphere};
+use crate::hana::ray::Ray;
+use crate::hana::shake::Shake;
 
+// what the world draw path needs from the camera to cull and pick lods
+pub struct View {
+  pub frustum: Frustum,
+  pub eye: Vec3,
+  // proj[1][1], the screen is 2 / scale tall at a distance of 1, or everywhere when ortho
+  pub scale: f32,
+  pub ortho: bool
+}
+
+impl View {
+  // fraction of the screen height the sphere covers
+  pub fn coverage(&self, sphere: &Sphere) -> f32 {
+    match self.ortho {
+      true => sphere.radius * self.scale,
+      false => sphere.radius * self.scale / sphere.center.distance(self.eye).max(1e-4)
+    }
+  }
+}
+
+// degrees of pitch for Camera::axonometric. true isometric, and the 2:1 pixel art kind
+pub const ISOMETRIC: f32 = 35.264;
+pub const DIMETRIC: f32 = 30.;
+
+// all of them map depth to 0..1, main sets clip control to match
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+  Perspective,
+  // no far plane, depth goes from 1 at near to 0 at infinity. see Projection::depth
+  ReversedZ,
+  // `height` world units tall. sees from far behind the camera to far in front of it,
+  // so pos is the middle of the view rather than where it is looked at from
+  Ortho { height: f32 }
+}
+
+impl Projection {
+  // depth func and clear depth to draw with
+  pub fn depth(&self) -> (u32, f64) {
+    match self {
+      Projection::ReversedZ => (gl::GREATER, 0.),
+      _ => (gl::LESS, 1.)
+    }
+  }
+}
+
+// where the camera is and what it sees, see cine::CamPath
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+  pub pos: Vec3,
+  pub rot: Quat,
+  // degrees, vertical
+  pub fov: f32
+}
+
+// third person, orbiting a target with the mouse. see Camera::follow
+#[derive(Clone)]
+pub struct Orbit {
+  pub distance: f32,
+  // how close it can get pulled in when something is in the way
+  pub min_distance: f32,
+  // degrees, negative looks down on the target
+  pub min_pitch: f32,
+  pub max_pitch: f32,
+  // the point orbited is this far above the target's origin
+  pub height: f32,
+  // fraction of the way to where it wants to be covered every tick, 1 sticks to the target
+  pub smoothing: f32,
+  // ticks of the target's velocity to lead it by
+  pub look_ahead: f32,
+  // kept between the camera and whatever it got pulled in by
+  pub radius: f32,
+  focus: Option<Vec3>,
+  last_target: Option<Vec3>,
+  current_distance: f32
+}
+
+impl Orbit {
+  pub fn new(distance: f32) -> Orbit {
+    Orbit {
+      distance,
+      min_distance: 0.5,
+      min_pitch: -70.,
+      max_pitch: 30.,
+      height: 1.,
+      smoothing: 0.3,
+      look_ahead: 4.,
+      radius: 0.2,
+      focus: None,
+      last_target: None,
+      current_distance: distance
+    }
+  }
+}
+
+#[derive(Clone)]
+pub enum Mode {
+  // flies where steer points it with velocity and friction, looks around with mouse_move
+  Free,
+  Orbit(Orbit)
+}
+
+// yaw and pitch take mouse input straight away, but like pos they only turn into the orientation
+// on the tick. renders slerp between the last two ticks' orientations, see Camera::tick
 #[derive(Clone)]
 pub struct Camera {
   pub pos: Vec3,
   pub prev_pos: Vec3,
+  // maps +z to front and +y to up
+  pub rot: Quat,
+  pub prev_rot: Quat,
+  // rot's basis
   pub front: Vec3,
   pub right: Vec3,
   pub up: Vec3,
   pub world_up: Vec3,
   pub yaw: f32,
   pub pitch: f32,
+  // degrees, vertical. unused when ortho
   pub fov: f32,
+  pub projection: Projection,
+  pub near: f32,
+  pub far: f32,
+  // free movement, units per second
+  pub velocity: Vec3,
+  // top speed
   pub speed: f32,
-  pub sensitivity: f32
+  // units per second squared while a key is held
+  pub acceleration: f32,
+  // fraction of the velocity lost per second is 1 - e^-friction
+  pub friction: f32,
+  // multiplies speed and acceleration while sprinting
+  pub sprint: f32,
+  pub sensitivity: f32,
+  pub mode: Mode,
+  pub shake: Shake,
+  // x right, y up, z forward, from steer until the next tick
+  input: Vec3,
+  sprinting: bool
 }
 
 impl Camera {
   pub fn new() -> Camera {
-    Camera {
+    let mut res = Camera {
       pos: Vec3::ZERO,
       prev_pos: Vec3::ZERO,
-      front: Vec3::ZERO,
-      right: Vec3::ZERO,
+      rot: Quat::IDENTITY,
+      prev_rot: Quat::IDENTITY,
+      front: Vec3::Z,
+      right: Vec3::X,
       up: Vec3::Y,
       world_up: Vec3::Y,
       yaw: 0.,
       pitch: 0.,
       fov: 60.,
-      speed: 1.,
-      sensitivity: 0.3
-    }
+      projection: Projection::Perspective,
+      near: 0.1,
+      far: 100.,
+      velocity: Vec3::ZERO,
+      speed: 3.,
+      acceleration: 30.,
+      friction: 8.,
+      sprint: 2.,
+      sensitivity: 0.3,
+      mode: Mode::Free,
+      shake: Shake::new(0),
+      input: Vec3::ZERO,
+      sprinting: false
+    };
+
+    res.update();
+    res.prev_rot = res.rot;
+    res
   }
 
+  // rot and its basis from yaw and pitch
   pub fn update(&mut self) {
-    self.front = Vec3 {
+    let front = Vec3 {
       x: self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
       y: self.pitch.to_radians().sin(),
       z: self.yaw.to_radians().sin() * self.pitch.to_radians().cos(),
     };
 
-    self.up = self.right.cross(self.front).normalize();
-    self.right = self.world_up.cross(self.front).normalize();
+    let right = self.world_up.cross(front).normalize();
+    let up = front.cross(right);
+    self.rot = Quat::from_mat3(&Mat3::from_cols(right, up, front)).normalize();
+    (self.front, self.right, self.up) = (front, right, up);
   }
 
-  pub fn key(&mut self, key: Key) {
+  // call once per tick after steer, mouse_move is fine any time. the steering only lasts the one tick
+  pub fn tick(&mut self) {
+    self.prev_pos = self.pos;
+    self.prev_rot = self.rot;
     self.update();
+    self.shake.tick();
 
-    match key {
-      Key::W => {
-        self.pos += (self.front * Vec3::new(1., 0., 1.)).normalize() * 0.1;
-      }
-      Key::S => {
-        self.pos -= (self.front * Vec3::new(1., 0., 1.)).normalize() * 0.1;
-      }
-      Key::A => {
-        self.pos -= self.right * 0.1;
-      }
-      Key::D => {
-        self.pos += self.right * 0.1;
-      }
-      Key::Space => {
-        self.pos += self.world_up * 0.1;
-      }
-      Key::LeftShift => {
-        self.pos -= self.world_up * 0.1;
-      }
-      _ => {}
+    let (input, sprinting) = (self.input, self.sprinting);
+    (self.input, self.sprinting) = (Vec3::ZERO, false);
+    if let Mode::Orbit(_) = self.mode {
+      self.velocity = Vec3::ZERO;
+      return;
     }
+
+    // along the ground whatever the pitch, and no faster on diagonals
+    let forward = (self.front * Vec3::new(1., 0., 1.)).normalize_or_zero();
+    let wish = (self.right * input.x + self.world_up * input.y + forward * input.z).clamp_length_max(1.);
+    let boost = if sprinting { self.sprint } else { 1. };
+
+    self.velocity *= (-self.friction * TICK_LENGTH).exp();
+    self.velocity += wish * self.acceleration * boost * TICK_LENGTH;
+    self.velocity = self.velocity.clamp_length_max(self.speed * boost);
+    if self.velocity.length_squared() < 1e-6 {
+      self.velocity = Vec3::ZERO;
+    }
+
+    self.pos += self.velocity * TICK_LENGTH;
+  }
+
+  // where to fly this tick, x right, y up and z forward, each -1..1
+  pub fn steer(&mut self, input: Vec3, sprinting: bool) {
+    self.input = input;
+    self.sprinting = sprinting;
   }
 
   pub fn mouse_move(&mut self, mut x_off: f32, mut y_off: f32) {
@@ -77,24 +233,319 @@ impl Camera {
     self.yaw -= x_off;
     self.pitch -= y_off;
 
-    if self.pitch > 89.0 {
-      self.pitch = 89.0;
-    } else if self.pitch < -89.0 {
-      self.pitch = -89.0;
+    let (min, max) = match &self.mode {
+      Mode::Free => (-89.0, 89.0),
+      Mode::Orbit(orbit) => (orbit.min_pitch, orbit.max_pitch)
+    };
+
+    self.pitch = self.pitch.clamp(min, max);
+  }
+
+  // switching back to free leaves the camera where it is
+  pub fn set_mode(&mut self, mode: Mode) {
+    if let Mode::Orbit(orbit) = &mode {
+      self.pitch = self.pitch.clamp(orbit.min_pitch, orbit.max_pitch);
     }
 
+    self.mode = mode;
     self.update();
   }
 
+  // moves an orbiting camera behind `pos`, call every tick once the target has moved. `raycast`
+  // gives the distance to whatever is hit within the max distance, e.g. World::raycast_except
+  // skipping the target itself. anything in the way pulls the camera in straight away, it eases back out
+  pub fn follow(&mut self, pos: Vec3, raycast: impl Fn(&Ray, f32) -> Option<f32>) {
+    self.update();
+    let Mode::Orbit(orbit) = &mut self.mode else {
+      return;
+    };
+
+    let velocity = orbit.last_target.map_or(Vec3::ZERO, |it| pos - it);
+    orbit.last_target = Some(pos);
+
+    let wanted = pos + Vec3::Y * orbit.height + velocity * orbit.look_ahead;
+    let focus = orbit.focus.map_or(wanted, |it| it.lerp(wanted, orbit.smoothing));
+    orbit.focus = Some(focus);
+
+    let hit = raycast(&Ray::new(focus, -self.front), orbit.distance + orbit.radius);
+    let clear = hit.map_or(orbit.distance, |it| (it - orbit.radius).max(orbit.min_distance));
+    orbit.current_distance = match clear < orbit.current_distance {
+      true => clear,
+      false => orbit.current_distance + (clear - orbit.current_distance) * orbit.smoothing
+    };
+
+    self.pos = focus - self.front * orbit.current_distance;
+  }
+
+  pub fn pose(&self, tick_delta: f32) -> Pose {
+    Pose { pos: self.eye(tick_delta), rot: self.rot_at(tick_delta), fov: self.fov }
+  }
+
+  // moves to `pose` for this tick, renders still blend in from the last one. yaw and pitch
+  // follow along so free flying picks up from here, the next tick drops any roll
+  pub fn set_pose(&mut self, pose: &Pose) {
+    self.pos = pose.pos;
+    self.rot = pose.rot.normalize();
+    self.fov = pose.fov;
+    (self.front, self.right, self.up) = (self.rot * Vec3::Z, self.rot * Vec3::X, self.rot * Vec3::Y);
+    self.yaw = self.front.z.atan2(self.front.x).to_degrees();
+    self.pitch = self.front.y.clamp(-1., 1.).asin().to_degrees();
+  }
+
+  // set_pose without blending in, for jumps
+  pub fn cut(&mut self, pose: &Pose) {
+    self.set_pose(pose);
+    self.prev_pos = self.pos;
+    self.prev_rot = self.rot;
+  }
+
   pub fn eye(&self, tick_delta: f32) -> Vec3 {
     Vec3::lerp(self.prev_pos, self.pos, tick_delta)
   }
 
+  pub fn rot_at(&self, tick_delta: f32) -> Quat {
+    self.prev_rot.slerp(self.rot, tick_delta)
+  }
+
+  // with the shake on top, unlike eye and rot_at
   pub fn look_at(&self, tick_delta: f32) -> Mat4 {
-    Mat4::look_at_lh(self.eye(tick_delta), self.eye(tick_delta) + self.front, self.world_up)
+    let (eye, rot) = (self.eye(tick_delta), self.rot_at(tick_delta));
+    let (offset, shake) = self.shake.offset(tick_delta);
+    let (eye, rot) = (eye + rot * offset, rot * shake);
+    Mat4::look_at_lh(eye, eye + rot * Vec3::Z, rot * Vec3::Y)
   }
 
   pub fn proj(&self, aspect: f32) -> Mat4 {
-    Mat4::perspective_lh(self.fov.to_radians(), aspect, 0.1, 100.0)
+    match self.projection {
+      Projection::Perspective => Mat4::perspective_lh(self.fov.to_radians(), aspect, self.near, self.far),
+      Projection::ReversedZ => Mat4::perspective_infinite_reverse_lh(self.fov.to_radians(), aspect, self.near),
+      Projection::Ortho { height } => {
+        let (x, y) = (height * 0.5 * aspect, height * 0.5);
+        Mat4::orthographic_lh(-x, x, -y, y, -self.far, self.far)
+      }
+    }
+  }
+
+  // positive zooms in, by narrowing the fov or shrinking the ortho height
+  pub fn zoom(&mut self, steps: f32) {
+    let scale = 0.9f32.powf(steps);
+    match &mut self.projection {
+      Projection::Ortho { height } => *height = (*height * scale).clamp(1., 1000.),
+      _ => self.fov = (self.fov * scale).clamp(5., 120.)
+    }
+  }
+
+  // ortho, looking down at `pitch` degrees along the nearest diagonal. see ISOMETRIC and DIMETRIC
+  pub fn axonometric(&mut self, pitch: f32, height: f32) {
+    self.projection = Projection::Ortho { height };
+    self.yaw = ((self.yaw - 45.) / 90.).round() * 90. + 45.;
+    self.pitch = -pitch;
+    self.update();
+  }
+
+  // what it draws to the lighting pass, w = 1 for a position to look from and w = 0 for a direction towards the eye
+  pub fn eye_or_dir(&self, tick_delta: f32) -> Vec4 {
+    match self.projection {
+      Projection::Ortho { .. } => (self.rot_at(tick_delta) * -Vec3::Z).extend(0.),
+      _ => self.eye(tick_delta).extend(1.)
+    }
+  }
+
+  pub fn frustum(&self, aspect: f32, tick_delta: f32) -> Frustum {
+    Frustum::from_mat(&(self.proj(aspect) * self.look_at(tick_delta)))
+  }
+
+  pub fn view(&self, aspect: f32, tick_delta: f32) -> View {
+    View {
+      frustum: self.frustum(aspect, tick_delta),
+      eye: self.eye(tick_delta),
+      scale: self.proj(aspect).y_axis.y,
+      ortho: matches!(self.projection, Projection::Ortho { .. })
+    }
+  }
+
+  // through `point` in window pixels from the top left, starting on the near plane. dir is normalised
+  pub fn ray(&self, point: Vec2, size: Vec2, tick_delta: f32) -> Ray {
+    let ndc = Vec2::new(point.x / size.x * 2. - 1., 1. - point.y / size.y * 2.);
+    let inv = (self.proj(size.x / size.y) * self.look_at(tick_delta)).inverse();
+    // the far plane can be at infinity, halfway is always somewhere in front
+    let near_z = if self.projection == Projection::ReversedZ { 1. } else { 0. };
+    let near = inv.project_point3(ndc.extend(near_z));
+    let far = inv.project_point3(ndc.extend(0.5));
+    Ray::new(near, (far - near).normalize())
+  }
+}
+#[cfg(test)]
+mod tests {
+  use glam::{Mat4, Vec3};
+  use glam::Vec2;
+  use crate::hana::bounds::Sphere;
+  use super::{Camera, Mode, Orbit, Projection, ISOMETRIC};
+
+  fn approx(a: Vec3, b: Vec3) -> bool {
+    a.abs_diff_eq(b, 1e-5)
+  }
+
+  fn aimed(yaw: f32, pitch: f32) -> Camera {
+    let mut cam = Camera::new();
+    (cam.yaw, cam.pitch) = (yaw, pitch);
+    cam.pos = Vec3::new(1., 2., 3.);
+    cam.tick();
+    cam
+  }
+
+  #[test]
+  fn basis_is_orthonormal() {
+    for (yaw, pitch) in [(0., 0.), (37., 12.), (-120., -80.), (200., 89.)] {
+      let cam = aimed(yaw, pitch);
+      for it in [cam.front, cam.right, cam.up] {
+        assert!((it.length() - 1.).abs() < 1e-5);
+      }
+
+      assert!(cam.front.dot(cam.right).abs() < 1e-5);
+      assert!(cam.front.dot(cam.up).abs() < 1e-5);
+      assert!(cam.right.dot(cam.up).abs() < 1e-5);
+      assert!(cam.up.y > 0.);
+      assert!(approx(cam.rot * Vec3::Z, cam.front));
+      assert!(approx(cam.rot * Vec3::Y, cam.up));
+    }
+  }
+
+  #[test]
+  fn fresh_camera_is_upright() {
+    let mut cam = Camera::new();
+    cam.tick();
+    assert!(approx(cam.up, Vec3::Y));
+    assert!(approx(cam.front, Vec3::X));
+    assert!(approx(cam.rot_at(0.) * Vec3::Z, Vec3::X));
+  }
+
+  #[test]
+  fn view_looks_down_front() {
+    let cam = aimed(30., 20.);
+    let view = cam.look_at(1.);
+    let eye = cam.eye(1.);
+    assert!(approx(view.transform_point3(eye), Vec3::ZERO));
+    assert!(approx(view.transform_point3(eye + cam.front), Vec3::Z));
+    assert!(approx(view.transform_point3(eye + cam.up), Vec3::Y));
+  }
+
+  #[test]
+  fn interpolates_between_ticks() {
+    let mut cam = aimed(0., 0.);
+    let prev = cam.look_at(1.);
+    cam.yaw = 90.;
+    cam.tick();
+    cam.pos += Vec3::X;
+
+    let at = |t: f32| cam.look_at(t);
+    assert!(at(0.).abs_diff_eq(prev, 1e-5));
+    assert!(at(1.).abs_diff_eq(Mat4::look_at_lh(cam.pos, cam.pos + cam.front, cam.up), 1e-5));
+
+    let half = aimed(45., 0.);
+    let eye = cam.eye(0.5);
+    assert!(at(0.5).abs_diff_eq(Mat4::look_at_lh(eye, eye + half.front, half.up), 1e-5));
+  }
+
+  #[test]
+  fn mouse_waits_for_the_tick() {
+    let mut cam = aimed(0., 0.);
+    let before = cam.look_at(1.);
+    cam.mouse_move(100., 0.);
+    assert!(cam.look_at(1.).abs_diff_eq(before, 1e-6));
+    cam.tick();
+    assert!(!cam.look_at(1.).abs_diff_eq(before, 1e-3));
+  }
+
+  fn hold(cam: &mut Camera, input: Vec3, sprinting: bool, ticks: usize) {
+    for _ in 0..ticks {
+      cam.steer(input, sprinting);
+      cam.tick();
+    }
+  }
+
+  #[test]
+  fn diagonals_are_not_faster() {
+    let (mut straight, mut diagonal) = (aimed(0., 0.), aimed(0., 0.));
+    hold(&mut straight, Vec3::Z, false, 60);
+    hold(&mut diagonal, Vec3::new(1., 0., 1.), false, 60);
+    assert!((straight.velocity.length() - straight.speed).abs() < 1e-4);
+    assert!((diagonal.velocity.length() - straight.velocity.length()).abs() < 1e-4);
+  }
+
+  #[test]
+  fn accelerates_sprints_and_stops() {
+    let mut cam = aimed(0., -60.);
+    hold(&mut cam, Vec3::Z, false, 1);
+    assert!(cam.velocity.length() < cam.speed);
+    assert_eq!(cam.velocity.y, 0.);
+
+    hold(&mut cam, Vec3::Z, true, 60);
+    assert!((cam.velocity.length() - cam.speed * cam.sprint).abs() < 1e-4);
+
+    hold(&mut cam, Vec3::ZERO, false, 60);
+    assert_eq!(cam.velocity, Vec3::ZERO);
+    assert_eq!(cam.pos, cam.prev_pos);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn every_projection_sees_ahead() {
+    for projection in [Projection::Perspective, Projection::ReversedZ, Projection::Ortho { height: 10. }] {
+      let mut cam = aimed(30., -20.);
+      cam.projection = projection;
+      cam.tick();
+
+      let size = Vec2::new(160., 90.);
+      let ray = cam.ray(size * 0.5, size, 1.);
+      assert!(approx(ray.dir, cam.front));
+
+      let view = cam.view(size.x / size.y, 1.);
+      assert!(view.frustum.contains_sphere(&Sphere { center: cam.pos + cam.front * 50., radius: 0.1 }));
+      assert!(!view.frustum.contains_sphere(&Sphere { center: cam.pos + cam.right * 1000., radius: 0.1 }));
+    }
+  }
+
+  #[test]
+  fn axonometric_snaps_to_a_diagonal() {
+    let mut cam = aimed(10., 0.);
+    cam.axonometric(ISOMETRIC, 10.);
+    assert_eq!((cam.yaw, cam.pitch), (45., -ISOMETRIC));
+    assert_eq!(cam.projection, Projection::Ortho { height: 10. });
+    cam.zoom(1.);
+    assert_eq!(cam.projection, Projection::Ortho { height: 9. });
+  }
+
+  #[test]
+  fn shake_only_moves_the_view() {
+    let mut cam = aimed(0., 0.);
+    let steady = cam.look_at(1.);
+    cam.shake.add(0.8);
+    hold(&mut cam, Vec3::ZERO, false, 3);
+    assert!(!cam.look_at(0.5).abs_diff_eq(steady, 1e-4));
+    assert!(approx(cam.pos, Vec3::new(1., 2., 3.)));
+    assert!(approx(cam.rot * Vec3::Z, Vec3::X));
+
+    hold(&mut cam, Vec3::ZERO, false, 30);
+    assert_eq!(cam.shake.trauma, 0.);
+    assert!(cam.look_at(1.).abs_diff_eq(steady, 1e-6));
+  }
+
+  #[test]
+  fn follow_pulls_in_and_eases_out() {
+    let mut cam = aimed(0., 0.);
+    cam.set_mode(Mode::Orbit(Orbit::new(5.)));
+    let focus = Vec3::Y;
+    cam.follow(Vec3::ZERO, |_, _| None);
+    assert!(approx(cam.pos, focus - Vec3::X * 5.));
+
+    // something 2 behind, kept the radius away from it
+    cam.follow(Vec3::ZERO, |ray, max_t| {
+      assert!(approx(ray.dir, Vec3::NEG_X) && max_t > 5.);
+      Some(2.)
+    });
+    assert!((cam.pos.distance(focus) - 1.8).abs() < 1e-5);
+
+    cam.follow(Vec3::ZERO, |_, _| None);
+    assert!((cam.pos.distance(focus) - (1.8 + 3.2 * 0.3)).abs() < 1e-5);
+  }
+}
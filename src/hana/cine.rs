@@ -0,0 +1,280 @@
+use std::fs;
+use glam::{Quat, Vec3};
+use crate::hana::camera::{Camera, Pose};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ease {
+  Linear,
+  In,
+  Out,
+  InOut
+}
+
+impl Ease {
+  // t in 0..1
+  pub fn apply(self, t: f32) -> f32 {
+    match self {
+      Ease::Linear => t,
+      Ease::In => t * t,
+      Ease::Out => t * (2. - t),
+      Ease::InOut => t * t * (3. - 2. * t)
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Ease::Linear => "linear",
+      Ease::In => "in",
+      Ease::Out => "out",
+      Ease::InOut => "in_out"
+    }
+  }
+
+  fn parse(name: &str) -> Option<Ease> {
+    [Ease::Linear, Ease::In, Ease::Out, Ease::InOut].into_iter().find(|it| it.name() == name)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spline {
+  // passes through every key
+  CatmullRom,
+  // cubic pieces, keys 0, 3, 6.. are passed through and the two between each pair only pull
+  // the curve towards them. the handles' times are ignored, leftovers past the last full piece too
+  Bezier
+}
+
+impl Spline {
+  fn name(self) -> &'static str {
+    match self {
+      Spline::CatmullRom => "catmull_rom",
+      Spline::Bezier => "bezier"
+    }
+  }
+
+  fn parse(name: &str) -> Option<Spline> {
+    [Spline::CatmullRom, Spline::Bezier].into_iter().find(|it| it.name() == name)
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+  // seconds from the start of the path
+  pub time: f32,
+  pub pose: Pose,
+  // shapes the piece from this key to the next
+  pub ease: Ease
+}
+
+// pos, rot and fov as one vector so the splines only need writing once
+type Flat = [f32; 8];
+
+fn flatten(pose: &Pose, like: Quat) -> Flat {
+  // the closer of q and -q, otherwise the blend takes the long way round
+  let rot = if pose.rot.dot(like) < 0. { -pose.rot } else { pose.rot };
+  [pose.pos.x, pose.pos.y, pose.pos.z, rot.x, rot.y, rot.z, rot.w, pose.fov]
+}
+
+fn unflatten(it: Flat) -> Pose {
+  Pose {
+    pos: Vec3::new(it[0], it[1], it[2]),
+    rot: Quat::from_xyzw(it[3], it[4], it[5], it[6]).normalize(),
+    fov: it[7]
+  }
+}
+
+fn mix(weights: [f32; 4], points: [Flat; 4]) -> Flat {
+  let mut res = [0.; 8];
+  for (w, point) in weights.iter().zip(points) {
+    for (it, val) in res.iter_mut().zip(point) {
+      *it += w * val;
+    }
+  }
+
+  res
+}
+
+fn catmull_rom(t: f32) -> [f32; 4] {
+  let (t2, t3) = (t * t, t * t * t);
+  [
+    0.5 * (-t + 2. * t2 - t3),
+    0.5 * (2. - 5. * t2 + 3. * t3),
+    0.5 * (t + 4. * t2 - 3. * t3),
+    0.5 * (-t2 + t3)
+  ]
+}
+
+fn bezier(t: f32) -> [f32; 4] {
+  let s = 1. - t;
+  [s * s * s, 3. * s * s * t, 3. * s * t * t, t * t * t]
+}
+
+// a trailer shot, recorded from the free camera and played back on the fixed tick or frame by frame
+#[derive(Clone, Debug)]
+pub struct CamPath {
+  pub spline: Spline,
+  // sorted by time
+  pub keys: Vec<Keyframe>
+}
+
+impl CamPath {
+  pub fn new(spline: Spline) -> CamPath {
+    CamPath { spline, keys: Vec::new() }
+  }
+
+  // when the last anchor is reached, Bezier handles and leftovers after it don't count
+  pub fn duration(&self) -> f32 {
+    self.keys.get(self.last_anchor()).map_or(0., |it| it.time)
+  }
+
+  fn stride(&self) -> usize {
+    match self.spline {
+      Spline::CatmullRom => 1,
+      Spline::Bezier => 3
+    }
+  }
+
+  // the last key the curve passes through
+  fn last_anchor(&self) -> usize {
+    (self.keys.len().max(1) - 1) / self.stride() * self.stride()
+  }
+
+  // the camera's current pose as a key at `time`
+  pub fn record(&mut self, cam: &Camera, time: f32, ease: Ease) {
+    let at = self.keys.partition_point(|it| it.time <= time);
+    self.keys.insert(at, Keyframe { time, pose: cam.pose(1.), ease });
+  }
+
+  // clamped to the first and last keys, None without any
+  pub fn sample(&self, time: f32) -> Option<Pose> {
+    let (stride, last) = (self.stride(), self.last_anchor());
+    let first = self.keys.first()?;
+    if self.keys.len() == 1 || time <= first.time {
+      return Some(first.pose);
+    }
+
+    if time >= self.keys[last].time {
+      return Some(self.keys[last].pose);
+    }
+
+    let n = (0..last).step_by(stride).take_while(|it| self.keys[*it].time <= time).count();
+    let start = (n - 1) * stride;
+    let (from, to) = (&self.keys[start], &self.keys[start + stride]);
+    let t = from.ease.apply(((time - from.time) / (to.time - from.time).max(1e-6)).clamp(0., 1.));
+
+    let like = from.pose.rot;
+    let point = |i: usize| flatten(&self.keys[i].pose, like);
+    let res = match self.spline {
+      Spline::CatmullRom => {
+        let before = start.saturating_sub(1);
+        let after = (start + 2).min(self.keys.len() - 1);
+        mix(catmull_rom(t), [point(before), point(start), point(start + 1), point(after)])
+      }
+      Spline::Bezier => mix(bezier(t), [point(start), point(start + 1), point(start + 2), point(start + 3)])
+    };
+
+    Some(unflatten(res))
+  }
+
+  // plain text, one key per line so shots can be touched up by hand
+  pub fn save(&self, path: &str) -> Result<(), String> {
+    let mut res = format!("# hana camera path\nspline {}\n", self.spline.name());
+    for it in &self.keys {
+      let (pos, rot) = (it.pose.pos, it.pose.rot);
+      res += &format!(
+        "key {} {} {} {} {} {} {} {} {} {}\n",
+        it.time, pos.x, pos.y, pos.z, rot.x, rot.y, rot.z, rot.w, it.pose.fov, it.ease.name()
+      );
+    }
+
+    fs::write(path, res).map_err(|e| path.to_string() + ": " + &e.to_string())
+  }
+
+  pub fn load(path: &str) -> Result<CamPath, String> {
+    let text = fs::read_to_string(path).map_err(|e| path.to_string() + ": " + &e.to_string())?;
+    let mut res = CamPath::new(Spline::CatmullRom);
+    for (n, line) in text.lines().enumerate() {
+      let err = |what: &str| format!("{}:{}: {}", path, n + 1, what);
+      let words = line.split_whitespace().collect::<Vec<_>>();
+      match words.as_slice() {
+        [] => {}
+        [it, ..] if it.starts_with('#') => {}
+        ["spline", name] => res.spline = Spline::parse(name).ok_or_else(|| err("unknown spline"))?,
+        ["key", nums @ .., ease] if nums.len() == 9 => {
+          let nums = nums.iter().map(|it| it.parse::<f32>()).collect::<Result<Vec<_>, _>>().map_err(|e| err(&e.to_string()))?;
+          let pose = Pose {
+            pos: Vec3::new(nums[1], nums[2], nums[3]),
+            rot: Quat::from_xyzw(nums[4], nums[5], nums[6], nums[7]).normalize(),
+            fov: nums[8]
+          };
+
+          let ease = Ease::parse(ease).ok_or_else(|| err("unknown ease"))?;
+          res.keys.push(Keyframe { time: nums[0], pose, ease });
+        }
+        _ => return Err(err("expected `spline <name>` or `key <time> <pos> <rot> <fov> <ease>`"))
+      }
+    }
+
+    res.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use glam::{Quat, Vec3};
+  use crate::hana::camera::Pose;
+  use super::{CamPath, Ease, Keyframe, Spline};
+
+  fn path(spline: Spline, n: usize) -> CamPath {
+    let mut res = CamPath::new(spline);
+    for i in 0..n {
+      let pose = Pose { pos: Vec3::new(i as f32, (i * i) as f32, -(i as f32)), rot: Quat::from_rotation_y(i as f32 * 0.3), fov: 60. + i as f32 };
+      res.keys.push(Keyframe { time: i as f32, pose, ease: if i % 2 == 0 { Ease::Linear } else { Ease::InOut } });
+    }
+
+    res
+  }
+
+  fn passes(path: &CamPath, key: usize) -> bool {
+    let (want, got) = (path.keys[key].pose, path.sample(path.keys[key].time).unwrap());
+    got.pos.abs_diff_eq(want.pos, 1e-4) && got.rot.abs_diff_eq(want.rot, 1e-4) && (got.fov - want.fov).abs() < 1e-4
+  }
+
+  #[test]
+  fn catmull_rom_passes_through_keys() {
+    let path = path(Spline::CatmullRom, 5);
+    assert!((0..5).all(|it| passes(&path, it)));
+    assert!(!path.sample(1.5).unwrap().pos.abs_diff_eq(path.keys[1].pose.pos, 1e-2));
+    assert_eq!(path.duration(), 4.);
+  }
+
+  #[test]
+  fn bezier_anchors() {
+    // two pieces and a leftover handle
+    let path = path(Spline::Bezier, 8);
+    assert!([0, 3, 6].into_iter().all(|it| passes(&path, it)));
+    // handles only pull the curve
+    assert!(!path.sample(1.).unwrap().pos.abs_diff_eq(path.keys[1].pose.pos, 1e-2));
+    assert_eq!(path.duration(), 6.);
+    assert!(path.sample(7.).unwrap().pos.abs_diff_eq(path.keys[6].pose.pos, 1e-4));
+    assert_eq!(CamPath::new(Spline::Bezier).duration(), 0.);
+  }
+
+  #[test]
+  fn save_and_load() {
+    let path = path(Spline::Bezier, 4);
+    let file = std::env::temp_dir().join("hana_cine_test.txt");
+    let file = file.to_str().unwrap();
+    path.save(file).unwrap();
+    let loaded = CamPath::load(file).unwrap();
+    std::fs::remove_file(file).unwrap();
+    assert_eq!(loaded.spline, path.spline);
+    assert_eq!(loaded.keys.len(), path.keys.len());
+    for (a, b) in loaded.keys.iter().zip(&path.keys) {
+      assert_eq!((a.time, a.ease), (b.time, b.ease));
+      assert!(a.pose.pos.abs_diff_eq(b.pose.pos, 1e-6) && a.pose.rot.abs_diff_eq(b.pose.rot, 1e-6));
+      assert_eq!(a.pose.fov, b.pose.fov);
+    }
+  }
+}
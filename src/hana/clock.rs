@@ -0,0 +1,207 @@
+use glfw::Glfw;
+use crate::hana::anim::TICK_LENGTH;
+
+// where GameLoop gets the time from, in seconds
+pub trait Clock {
+  fn now(&mut self) -> f64;
+}
+
+impl Clock for Glfw {
+  fn now(&mut self) -> f64 {
+    self.get_time()
+  }
+}
+
+// moves on by `step` every time it's read, or only when `time` is changed by hand with a step of 0
+pub struct FakeClock {
+  pub time: f64,
+  pub step: f64
+}
+
+impl FakeClock {
+  pub fn new(step: f64) -> FakeClock {
+    FakeClock { time: 0., step }
+  }
+}
+
+impl Clock for FakeClock {
+  fn now(&mut self) -> f64 {
+    let time = self.time;
+    self.time += self.step;
+    time
+  }
+}
+
+// what GameLoop::frame drives. the hooks hold the loop they're run by, so they can still pause or step it
+pub trait Hooks {
+  fn game(&mut self) -> &mut GameLoop;
+  // once a frame, before ticking
+  fn input(&mut self) {}
+  fn tick(&mut self);
+  // tick_delta is how far between the last two ticks to draw
+  fn render(&mut self, _tick_delta: f32) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+  pub ticks: u32,
+  pub tick_delta: f32
+}
+
+// fixed timestep of anim::TICK_LENGTH, which everything ticked steps by. frames run however many
+// ticks the time since the last one is worth, time_scale plays it faster or slower
+pub struct GameLoop {
+  // ticks a single frame catches up on at most, the rest are dropped rather than spiralling
+  pub max_ticks: u32,
+  pub paused: bool,
+  // multiplies how fast time passes, 0.5 is half speed
+  pub time_scale: f64,
+  // every tick run so far
+  pub ticks: u64,
+  // lost to max_ticks
+  pub dropped: u64,
+  prev_time: Option<f64>,
+  // ticks owed, the fraction is the tick delta
+  owed: f64,
+  steps: u32
+}
+
+impl GameLoop {
+  pub fn new() -> GameLoop {
+    GameLoop {
+      max_ticks: 10,
+      paused: false,
+      time_scale: 1.,
+      ticks: 0,
+      dropped: 0,
+      prev_time: None,
+      owed: 0.,
+      steps: 0
+    }
+  }
+
+  // one tick on the next frame, for going through frame by frame while paused
+  pub fn step(&mut self) {
+    self.steps += 1;
+  }
+
+  // how many ticks to run for the time now, the first call only starts the clock
+  pub fn advance(&mut self, now: f64) -> Step {
+    let elapsed = self.prev_time.map_or(0., |it| (now - it).max(0.));
+    self.prev_time = Some(now);
+    if !self.paused {
+      self.owed += elapsed * self.time_scale / TICK_LENGTH as f64;
+    }
+
+    let mut ticks = self.owed.floor();
+    self.owed -= ticks;
+    if ticks > self.max_ticks as f64 {
+      self.dropped += ticks as u64 - self.max_ticks as u64;
+      ticks = self.max_ticks as f64;
+    }
+
+    let ticks = ticks as u32 + std::mem::take(&mut self.steps);
+    self.ticks += ticks as u64;
+    // stepping through while paused shows each tick as it is
+    let tick_delta = if self.paused { 1. } else { self.owed as f32 };
+    Step { ticks, tick_delta }
+  }
+
+  // input, the ticks `now` is worth and a render, on the hooks' own loop. `now` is from a Clock
+  pub fn frame(hooks: &mut impl Hooks, now: f64) -> Step {
+    hooks.input();
+    let step = hooks.game().advance(now);
+    for _ in 0..step.ticks {
+      hooks.tick();
+    }
+
+    hooks.render(step.tick_delta);
+    step
+  }
+}
+
+impl Default for GameLoop {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::hana::anim::TICK_LENGTH;
+  use super::{Clock, FakeClock, GameLoop, Hooks, Step};
+
+  const T: f64 = TICK_LENGTH as f64;
+
+  #[derive(Default)]
+  struct Log(GameLoop, Vec<String>);
+
+  impl Hooks for Log {
+    fn game(&mut self) -> &mut GameLoop {
+      &mut self.0
+    }
+
+    fn input(&mut self) {
+      self.1.push("input".into());
+    }
+
+    fn tick(&mut self) {
+      self.1.push("tick".into());
+    }
+
+    fn render(&mut self, tick_delta: f32) {
+      self.1.push(format!("render {:.2}", tick_delta));
+    }
+  }
+
+  fn approx(step: Step, ticks: u32, tick_delta: f32) -> bool {
+    step.ticks == ticks && (step.tick_delta - tick_delta).abs() < 1e-4
+  }
+
+  #[test]
+  fn fake_clock_steps() {
+    let mut clock = FakeClock::new(0.25);
+    assert_eq!([clock.now(), clock.now(), clock.now()], [0., 0.25, 0.5]);
+  }
+
+  #[test]
+  fn ticks_and_tick_delta() {
+    let mut game = GameLoop::new();
+    assert!(approx(game.advance(5.), 0, 0.));
+    assert!(approx(game.advance(5. + 2.5 * T), 2, 0.5));
+    assert!(approx(game.advance(5. + 3.25 * T), 1, 0.25));
+    assert_eq!(game.ticks, 3);
+  }
+
+  #[test]
+  fn catching_up_is_capped() {
+    let mut game = GameLoop::new();
+    game.max_ticks = 4;
+    game.advance(0.);
+    assert!(approx(game.advance(10.5 * T), 4, 0.5));
+    assert_eq!(game.dropped, 6);
+  }
+
+  #[test]
+  fn pause_step_and_scale() {
+    let mut game = GameLoop::new();
+    game.advance(0.);
+    game.paused = true;
+    assert!(approx(game.advance(1.), 0, 1.));
+    game.step();
+    assert!(approx(game.advance(1.), 1, 1.));
+    assert!(approx(game.advance(2.), 0, 1.));
+
+    game.paused = false;
+    game.time_scale = 0.5;
+    assert!(approx(game.advance(2. + 5. * T), 2, 0.5));
+  }
+
+  #[test]
+  fn hooks_run_in_order() {
+    let (mut clock, mut log) = (FakeClock::new(2.5 * T), Log::default());
+    GameLoop::frame(&mut log, clock.now());
+    GameLoop::frame(&mut log, clock.now());
+    assert_eq!(log.1, ["input", "render 0.00", "input", "tick", "tick", "render 0.50"]);
+  }
+}
@@ -1,5 +1,6 @@
-use glam::{IVec2, Vec2, Vec3};
-use russimp::{Vector2D, Vector3D};
+use glam::{IVec2, Mat4, Quat, Vec2, Vec3};
+use russimp::animation::Quaternion;
+use russimp::{Matrix4x4, Vector2D, Vector3D};
 
 pub trait ScuffedInto<T> {
   fn cvt(&self) -> T;
@@ -21,4 +22,23 @@ impl ScuffedInto<IVec2> for Vec2 {
   fn cvt(&self) -> IVec2 {
     IVec2::new(self.x as i32, self.y as i32)
   }
+}
+
+impl ScuffedInto<Mat4> for Matrix4x4 {
+  fn cvt(&self) -> Mat4 {
+    // assimp matrices are row major
+    Mat4::from_cols_array(&[
+      self.a1, self.b1, self.c1, self.d1,
+      self.a2, self.b2, self.c2, self.d2,
+      self.a3, self.b3, self.c3, self.d3,
+      self.a4, self.b4, self.c4, self.d4,
+    ])
+  }
+}
+
+impl ScuffedInto<Quat> for Quaternion {
+  fn cvt(&self) -> Quat {
+    // mirrored across x like the vertices, which flips the rotation's handedness
+    Quat::from_xyzw(self.x, -self.y, -self.z, self.w)
+  }
 }
\ No newline at end of file
@@ -1,38 +1,207 @@
-use glam::Vec3;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use glam::{Mat4, Vec3};
+use crate::hana::anim::Animator;
+use crate::hana::batch::Instance;
+use crate::hana::bounds::{Aabb, CullStats};
+use crate::hana::camera::View;
+use crate::hana::glu::Shader;
 use crate::hana::model::Model;
+use crate::hana::ray::{Hit, Ray};
+use crate::hana::transform::{norm_mat, Transform};
 
+// tint overrides the model's own tints when >= 0
 pub enum Object {
-  Any { pos: Vec3, model: Model },
-  Player { pos: Vec3, model: Model },
+  Any { transform: Transform, parent: Option<Weak<RefCell<Object>>>, model: Rc<Model>, anim: Animator, tint: i32 },
+  Player { transform: Transform, parent: Option<Weak<RefCell<Object>>>, model: Rc<Model>, anim: Animator, tint: i32 },
 }
 
 impl Object {
   pub fn tick(&mut self) {
     match self {
-      Object::Any { .. } => {}
-      Object::Player { .. } => {}
+      Object::Any { anim, .. } => {
+        anim.tick()
+      }
+      Object::Player { anim, .. } => {
+        anim.tick()
+      }
     }
   }
 
-  pub fn draw(&self, tick_delta: f32) {
+  pub fn draw(&self, shader: &Shader, tick_delta: f32, cull: Option<(&View, &mut CullStats)>) {
+    let (model, anim) = self.model_anim();
+    let world = self.world_matrix();
+    shader.set_1i("u_tint_override", self.tint());
+    if anim.current.is_none() && !anim.morphing() {
+      match cull {
+        Some((view, stats)) => model.draw_culled(shader, &world, view, stats),
+        None => model.draw(shader, &world)
+      };
+    } else {
+      model.draw_posed(shader, &world, &anim.pose(model, tick_delta), &anim.morph_weights(tick_delta), cull);
+    }
+
+    shader.set_1i("u_tint_override", -1);
+  }
+
+  // animated or morphed objects need their own pose and can't be instanced
+  pub fn instance(&self, id: u32) -> Option<Instance> {
+    let anim = self.model_anim().1;
+    if anim.current.is_some() || anim.morphing() {
+      return None;
+    }
+
+    Some(Instance { model: self.world_matrix().to_cols_array(), tint: self.tint(), id })
+  }
+
+  pub fn tint(&self) -> i32 {
     match self {
-      Object::Any { .. } => {
+      Object::Any { tint, .. } => {
+        *tint
+      }
+      Object::Player { tint, .. } => {
+        *tint
+      }
+    }
+  }
+
+  pub fn bounds(&self) -> Aabb {
+    self.model().bounds.transform(&self.world_matrix())
+  }
 
+  pub fn model(&self) -> &Rc<Model> {
+    self.model_anim().0
+  }
+
+  pub fn anim(&self) -> &Animator {
+    self.model_anim().1
+  }
+
+  fn model_anim(&self) -> (&Rc<Model>, &Animator) {
+    match self {
+      Object::Any { model, anim, .. } => {
+        (model, anim)
       }
-      Object::Player { .. } => {
+      Object::Player { model, anim, .. } => {
+        (model, anim)
+      }
+    }
+  }
 
+  fn model_anim_mut(&mut self) -> (&Model, &mut Animator) {
+    match self {
+      Object::Any { model, anim, .. } => {
+        (&**model, anim)
+      }
+      Object::Player { model, anim, .. } => {
+        (&**model, anim)
       }
     }
   }
 
+  pub fn play(&mut self, clip: &str, looping: bool) -> Result<(), String> {
+    let (model, anim) = self.model_anim_mut();
+    anim.play(find_clip(model, clip)?, looping);
+    Ok(())
+  }
+
+  pub fn crossfade(&mut self, clip: &str, looping: bool, ticks: u32) -> Result<(), String> {
+    let (model, anim) = self.model_anim_mut();
+    anim.crossfade(find_clip(model, clip)?, looping, ticks);
+    Ok(())
+  }
+
+  pub fn blend(&mut self, clip: &str, looping: bool, weight: f32) -> Result<(), String> {
+    let (model, anim) = self.model_anim_mut();
+    anim.blend(find_clip(model, clip)?, looping, weight);
+    Ok(())
+  }
+
+  // eases the named morph to `weight` over `ticks`, on the same tick as the clips
+  pub fn morph(&mut self, name: &str, weight: f32, ticks: u32) -> Result<(), String> {
+    let (model, anim) = self.model_anim_mut();
+    let slot = model.morph(name).ok_or_else(|| format!("no morph named {}", name))?;
+    anim.morph(slot, weight, ticks);
+    Ok(())
+  }
+
   pub fn pos(&self) -> &Vec3 {
+    &self.transform().pos
+  }
+
+  pub fn transform(&self) -> &Transform {
+    match self {
+      Object::Any { transform, .. } => {
+        transform
+      }
+      Object::Player { transform, .. } => {
+        transform
+      }
+    }
+  }
+
+  pub fn transform_mut(&mut self) -> &mut Transform {
+    match self {
+      Object::Any { transform, .. } => {
+        transform
+      }
+      Object::Player { transform, .. } => {
+        transform
+      }
+    }
+  }
+
+  pub fn parent(&self) -> Option<Rc<RefCell<Object>>> {
+    match self {
+      Object::Any { parent, .. } => {
+        parent.as_ref().and_then(Weak::upgrade)
+      }
+      Object::Player { parent, .. } => {
+        parent.as_ref().and_then(Weak::upgrade)
+      }
+    }
+  }
+
+  pub fn set_parent(&mut self, new_parent: Option<&Rc<RefCell<Object>>>) {
+    let new_parent = new_parent.map(Rc::downgrade);
     match self {
-      Object::Any { pos, .. } => {
-        pos
+      Object::Any { parent, .. } => {
+        *parent = new_parent
       }
-      Object::Player { pos, .. } => {
-        pos
+      Object::Player { parent, .. } => {
+        *parent = new_parent
       }
     }
   }
-}
\ No newline at end of file
+
+  pub fn world_matrix(&self) -> Mat4 {
+    let local = self.transform().matrix();
+    match self.parent() {
+      Some(parent) => parent.borrow().world_matrix() * local,
+      None => local
+    }
+  }
+
+  pub fn world_pos(&self) -> Vec3 {
+    self.world_matrix().w_axis.truncate()
+  }
+
+  // world space ray and hit, along with the mesh that was hit. animated objects are hit
+  // in their latest tick's pose
+  pub fn raycast(&self, ray: &Ray, max_t: f32) -> Option<(usize, Hit)> {
+    let (model, anim) = self.model_anim();
+    let world = self.world_matrix();
+    let globals = match anim.current {
+      Some(_) => model.globals_posed(&anim.pose(model, 1.)),
+      None => model.globals()
+    };
+
+    let (mesh, hit) = model.raycast(&ray.transform(&world.inverse()), &globals, max_t)?;
+    let norm = (norm_mat(&world) * hit.norm).normalize_or_zero();
+    Some((mesh, Hit { pos: world.transform_point3(hit.pos), norm, ..hit }))
+  }
+}
+
+fn find_clip(model: &Model, name: &str) -> Result<usize, String> {
+  model.clip(name).ok_or_else(|| format!("no clip named {}", name))
+}
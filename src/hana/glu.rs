@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use std::ffi::{c_void, CStr};
 use std::fs;
-use std::ptr::{addr_of, addr_of_mut, null_mut};
+use std::path::Path;
+use std::ptr::{addr_of, addr_of_mut, null, null_mut};
 use std::sync::atomic::{AtomicI32, Ordering};
-use glam::{Mat4, Vec2, Vec3, Vec4};
+use glam::{IVec2, Mat3, Mat4, Vec2, Vec3, Vec4};
 use glfw::Window;
+use crate::hana::palette::Palette;
 
 pub fn gl_viewport(width: i32, height: i32) {
   unsafe { gl::Viewport(0, 0, width, height); }
@@ -18,6 +20,14 @@ pub fn gl_depth_func(func: u32) {
   unsafe { gl::DepthFunc(func) }
 }
 
+pub fn gl_clear_depth(depth: f64) {
+  unsafe { gl::ClearDepth(depth) }
+}
+
+pub fn gl_clip_control(origin: u32, depth: u32) {
+  unsafe { gl::ClipControl(origin, depth) }
+}
+
 pub struct Vao(u32);
 
 impl Vao {
@@ -54,6 +64,86 @@ impl Buf {
       gl::NamedBufferData(self.id, (data.len() * std::mem::size_of::<DataType>()) as isize, data.as_ptr() as *const _, usage)
     }
   }
+
+  // uninitialized, fill it with sub_data
+  pub fn storage(&self, usage: u32, bytes: usize) {
+    unsafe { gl::NamedBufferData(self.id, bytes as isize, null(), usage) }
+  }
+
+  // offset is in bytes
+  pub fn sub_data<DataType>(&self, offset: usize, data: &[DataType])
+    where DataType: Sized {
+    unsafe {
+      gl::NamedBufferSubData(self.id, offset as isize, (data.len() * std::mem::size_of::<DataType>()) as isize, data.as_ptr() as *const _)
+    }
+  }
+
+  // for indexed targets like SHADER_STORAGE_BUFFER
+  pub fn bind_base(&self, index: u32) {
+    unsafe { gl::BindBufferBase(self.usage, index, self.id) }
+  }
+
+  pub fn delete(self) {
+    unsafe { gl::DeleteBuffers(1, &self.id) }
+  }
+
+  // offset is in bytes, blocks until the gpu is done writing
+  pub fn get_sub_data<DataType>(&self, offset: usize, data: &mut [DataType])
+    where DataType: Sized {
+    unsafe {
+      gl::GetNamedBufferSubData(self.id, offset as isize, (data.len() * std::mem::size_of::<DataType>()) as isize, data.as_mut_ptr() as *mut _)
+    }
+  }
+}
+
+pub struct Fence(gl::types::GLsync);
+
+impl Fence {
+  // signals once every command issued before it has finished
+  pub fn new() -> Fence {
+    Fence(unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) })
+  }
+
+  pub fn signaled(&self) -> bool {
+    let res = unsafe { gl::ClientWaitSync(self.0, gl::SYNC_FLUSH_COMMANDS_BIT, 0) };
+    res == gl::ALREADY_SIGNALED || res == gl::CONDITION_SATISFIED
+  }
+}
+
+impl Drop for Fence {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteSync(self.0) }
+  }
+}
+
+pub fn gl_copy_buffer(src: &Buf, dst: &Buf, bytes: usize) {
+  unsafe { gl::CopyNamedBufferSubData(src.id, dst.id, 0, 0, bytes as isize) }
+}
+
+// the source with every `#include "file"` line replaced by that file, paths are relative to
+// the file doing the including. `depth` is how many includes deep this is
+fn read_shader(path: &str, depth: u32) -> Result<String, String> {
+  if depth > 8 {
+    return Err(format!("{}: includes nested too deep", path));
+  }
+
+  let src = fs::read_to_string(path).map_err(|e| path.to_string() + ": " + &e.to_string())?;
+  let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+  let mut res = String::with_capacity(src.len());
+  for (i, line) in src.lines().enumerate() {
+    let Some(name) = line.trim().strip_prefix("#include") else {
+      res += line;
+      res += "\n";
+      continue;
+    };
+
+    let name = name.trim().strip_prefix('"').and_then(|it| it.strip_suffix('"')).ok_or_else(|| format!("{}:{}: expected #include \"file\"", path, i + 1))?;
+    res += &read_shader(&dir.join(name).to_string_lossy(), depth + 1)?;
+    // so compile errors after the include still point at the right line
+    res += &format!("\n#line {}\n", i + 2);
+  }
+
+  Ok(res)
 }
 
 static mut CURRENT_SHADER: *const Shader = null_mut();
@@ -65,10 +155,10 @@ pub struct Shader {
 
 impl Shader {
   pub fn new(vert: &'static str, frag: &'static str, geom: Option<&'static str>) -> Result<Shader, String> {
-    let vert_src = fs::read_to_string(vert).map_err(|e| e.to_string())?;
-    let frag_src = fs::read_to_string(frag).map_err(|e| e.to_string())?;
+    let vert_src = read_shader(vert, 0)?;
+    let frag_src = read_shader(frag, 0)?;
     let geom_src = if let Some(geom) = geom {
-      Some(fs::read_to_string(geom).map_err(|e| e.to_string())?.to_owned())
+      Some(read_shader(geom, 0)?)
     } else {
       None
     };
@@ -198,6 +288,17 @@ impl Shader {
     panic!("uniform {} not found", name);
   }
 
+  pub fn set_1ui(&self, name: &'static str, val: u32) {
+    if self.uniforms.contains_key(name) {
+      unsafe {
+        gl::Uniform1ui(self.uniforms[name], val);
+      }
+      return;
+    }
+
+    panic!("uniform {} not found", name);
+  }
+
   pub fn set_mat4(&self, name: &'static str, val: &Mat4) {
     if self.uniforms.contains_key(name) {
       unsafe {
@@ -209,6 +310,28 @@ impl Shader {
     panic!("uniform {} not found", name);
   }
 
+  pub fn set_mat4v(&self, name: &'static str, val: &[Mat4]) {
+    if self.uniforms.contains_key(name) {
+      unsafe {
+        gl::UniformMatrix4fv(self.uniforms[name], val.len() as i32, gl::FALSE, val.as_ptr() as *const f32);
+      }
+      return;
+    }
+
+    panic!("uniform {} not found", name);
+  }
+
+  pub fn set_mat3(&self, name: &'static str, val: &Mat3) {
+    if self.uniforms.contains_key(name) {
+      unsafe {
+        gl::UniformMatrix3fv(self.uniforms[name], 1, gl::FALSE, val.as_ref().as_ptr());
+      }
+      return;
+    }
+
+    panic!("uniform {} not found", name);
+  }
+
   pub fn bind(&self) {
     unsafe {
       gl::UseProgram(self.id);
@@ -249,15 +372,20 @@ impl Tex {
     let mut tex = 0;
     unsafe {
       gl::CreateTextures(gl::TEXTURE_2D, 1, addr_of_mut!(tex));
-      gl::TextureParameteri(tex, gl::TEXTURE_WRAP_S, gl::MIRRORED_REPEAT as i32);
-      gl::TextureParameteri(tex, gl::TEXTURE_WRAP_T, gl::MIRRORED_REPEAT as i32);
+      gl::TextureParameteri(tex, gl::TEXTURE_WRAP_S, spec.wrap as i32);
+      gl::TextureParameteri(tex, gl::TEXTURE_WRAP_T, spec.wrap as i32);
       gl::TextureParameteri(tex, gl::TEXTURE_MIN_FILTER, spec.min_filter as i32);
       gl::TextureParameteri(tex, gl::TEXTURE_MAG_FILTER, spec.mag_filter as i32);
 
-      gl::TextureStorage2D(tex, 1, spec.internal_format, spec.width, spec.height);
+      gl::TextureStorage2D(tex, spec.levels, spec.internal_format, spec.width, spec.height);
 
       if let Some(pixels) = &spec.pixels {
-        gl::TextureSubImage2D(tex, 0, 0, 0, spec.width, spec.height, spec.format, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const c_void)
+        // rows are tightly packed, e.g. one byte palette indices at any width
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TextureSubImage2D(tex, 0, 0, 0, spec.width, spec.height, spec.format, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const c_void);
+        if spec.levels > 1 {
+          gl::GenerateTextureMipmap(tex);
+        }
       }
     }
 
@@ -266,14 +394,40 @@ impl Tex {
 
   pub fn data<T>(&self, dat: &[T], width: i32, height: i32, format: u32, type_: u32)
   where T : Sized {
-    unsafe { gl::TextureSubImage2D(self.id, 0, 0, 0, width, height, format, type_, dat.as_ptr() as *const c_void) }
+    unsafe {
+      gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+      gl::TextureSubImage2D(self.id, 0, 0, 0, width, height, format, type_, dat.as_ptr() as *const c_void);
+    }
+  }
+}
+
+// component count, component type, and whether integer components get normalized to floats.
+// integer types that aren't normalized are read as ints (VertexArrayAttribIFormat)
+pub type Attrib = (i32, u32, bool);
+
+pub const FLOAT_1: Attrib = (1, gl::FLOAT, false);
+pub const FLOAT_2: Attrib = (2, gl::FLOAT, false);
+pub const FLOAT_3: Attrib = (3, gl::FLOAT, false);
+pub const FLOAT_4: Attrib = (4, gl::FLOAT, false);
+pub const INT_1: Attrib = (1, gl::INT, false);
+pub const INT_4: Attrib = (4, gl::INT, false);
+pub const HALF_2: Attrib = (2, gl::HALF_FLOAT, false);
+pub const SHORT_2_NORM: Attrib = (2, gl::SHORT, true);
+pub const USHORT_4_NORM: Attrib = (4, gl::UNSIGNED_SHORT, true);
+pub const UBYTE_4: Attrib = (4, gl::UNSIGNED_BYTE, false);
+pub const UBYTE_4_NORM: Attrib = (4, gl::UNSIGNED_BYTE, true);
+
+fn gl_type_size(type_: u32) -> i32 {
+  match type_ {
+    gl::BYTE | gl::UNSIGNED_BYTE => 1,
+    gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2,
+    _ => 4
   }
 }
 
-pub const FLOAT_1: (i32, bool) = (1, true);
-pub const FLOAT_2: (i32, bool) = (2, true);
-pub const FLOAT_3: (i32, bool) = (3, true);
-pub const FLOAT_4: (i32, bool) = (4, true);
+pub fn gl_stride(attribs: &[Attrib]) -> i32 {
+  attribs.iter().map(|(size, type_, _)| size * gl_type_size(*type_)).sum()
+}
 
 #[derive(Clone)]
 pub struct TexSpec {
@@ -283,6 +437,8 @@ pub struct TexSpec {
   pub format: u32,
   pub min_filter: u32,
   pub mag_filter: u32,
+  pub wrap: u32,
+  pub levels: i32,
   pub pixels: Option<Vec<u8>>,
 }
 
@@ -295,6 +451,8 @@ impl TexSpec {
       format: 0,
       min_filter: 0,
       mag_filter: 0,
+      wrap: 0,
+      levels: 0,
       pixels: None,
     }
   }
@@ -307,6 +465,8 @@ impl TexSpec {
       format: gl::RGBA,
       min_filter: gl::LINEAR,
       mag_filter: gl::LINEAR,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
       pixels: None,
     }
   }
@@ -327,6 +487,8 @@ impl TexSpec {
       format: gl::RGBA,
       min_filter: gl::LINEAR,
       mag_filter: gl::LINEAR,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
       pixels: None,
     }
   }
@@ -347,6 +509,8 @@ impl TexSpec {
       format: gl::RED,
       min_filter: gl::NEAREST,
       mag_filter: gl::NEAREST,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
       pixels: None,
     }
   }
@@ -375,6 +539,22 @@ impl TexSpec {
       format: gl::RED,
       min_filter: gl::NEAREST,
       mag_filter: gl::NEAREST,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
+      pixels: None,
+    }
+  }
+
+  pub fn r32ui_nearest(width: i32, height: i32) -> TexSpec {
+    TexSpec {
+      width,
+      height,
+      internal_format: gl::R32UI,
+      format: gl::RED_INTEGER,
+      min_filter: gl::NEAREST,
+      mag_filter: gl::NEAREST,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
       pixels: None,
     }
   }
@@ -387,11 +567,77 @@ impl TexSpec {
       format: gl::DEPTH_COMPONENT,
       min_filter: gl::NEAREST,
       mag_filter: gl::NEAREST,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
       pixels: None,
     }
   }
+
+  // reversed z needs the float precision, fixed point depth has none left near the far plane
+  pub fn depth32f_nearest(width: i32, height: i32) -> TexSpec {
+    TexSpec {
+      width,
+      height,
+      internal_format: gl::DEPTH_COMPONENT32F,
+      format: gl::DEPTH_COMPONENT,
+      min_filter: gl::NEAREST,
+      mag_filter: gl::NEAREST,
+      wrap: gl::MIRRORED_REPEAT,
+      levels: 1,
+      pixels: None,
+    }
+  }
+
+  pub fn mip_levels(width: i32, height: i32) -> i32 {
+    32 - width.max(height).max(1).leading_zeros() as i32
+  }
+
+  // png/tga as mipmapped rgba8, flipped so the first row is the bottom like gl expects
+  pub fn image(path: &str) -> Result<TexSpec, String> {
+    let img = image::open(path).map_err(|e| path.to_string() + ": " + &e.to_string())?.flipv().into_rgba8();
+    let (width, height) = (img.width() as i32, img.height() as i32);
+
+    Ok(TexSpec {
+      width,
+      height,
+      internal_format: gl::RGBA8,
+      format: gl::RGBA,
+      min_filter: gl::LINEAR_MIPMAP_LINEAR,
+      mag_filter: gl::LINEAR,
+      wrap: gl::REPEAT,
+      levels: Self::mip_levels(width, height),
+      pixels: Some(img.into_raw()),
+    })
+  }
+
+  // every texel becomes the index of its nearest palette ramp, transparent texels
+  // become UNPAINTED so the vertex tint shows through. integer textures can't be
+  // filtered, so this is always nearest without mips
+  pub fn palette_indexed(path: &str, palette: &Palette) -> Result<TexSpec, String> {
+    let rgba = Self::image(path)?;
+    let pixels = rgba.pixels.unwrap().chunks_exact(4).map(|it| {
+      if it[3] < 128 {
+        return UNPAINTED;
+      }
+
+      let color = Vec3::new(it[0] as f32, it[1] as f32, it[2] as f32) / 255.;
+      palette.nearest_ramp(color).clamp(0, UNPAINTED as i32 - 1) as u8
+    }).collect();
+
+    Ok(TexSpec {
+      internal_format: gl::R8UI,
+      format: gl::RED_INTEGER,
+      min_filter: gl::NEAREST,
+      mag_filter: gl::NEAREST,
+      levels: 1,
+      pixels: Some(pixels),
+      ..rgba
+    })
+  }
 }
 
+pub const UNPAINTED: u8 = 255;
+
 pub fn gl_clear(mask: u32) {
   unsafe { gl::Clear(mask) }
 }
@@ -423,6 +669,35 @@ impl Fbo {
     unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, self.id) }
   }
 
+  // integer attachments can't go through gl_clear, draw_buffer indexes the last draw_buffers call
+  pub fn clear_ui(&self, draw_buffer: i32, val: u32) {
+    unsafe { gl::ClearNamedFramebufferuiv(self.id, gl::COLOR, draw_buffer, [val, 0, 0, 0].as_ptr()) }
+  }
+
+  // copies one pixel into `dst` at `offset` bytes without waiting on the gpu, fence before reading it back
+  pub fn read_pixel(&self, attachment: u32, pos: IVec2, format: u32, type_: u32, dst: &Buf, offset: usize) {
+    unsafe {
+      gl::NamedFramebufferReadBuffer(self.id, attachment);
+      gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+      gl::BindBuffer(gl::PIXEL_PACK_BUFFER, dst.id);
+      gl::ReadPixels(pos.x, pos.y, 1, 1, format, type_, offset as *mut c_void);
+      gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+    }
+  }
+
+  // waits on the gpu. rgba8 rows bottom up, pass gl::BACK as the attachment for the window's
+  pub fn read_rgba8(&self, attachment: u32, width: i32, height: i32) -> Vec<u8> {
+    let mut res = vec![0u8; width as usize * height as usize * 4];
+    unsafe {
+      gl::NamedFramebufferReadBuffer(self.id, attachment);
+      gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+      gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+      gl::ReadPixels(0, 0, width, height, gl::RGBA, gl::UNSIGNED_BYTE, res.as_mut_ptr() as *mut c_void);
+    }
+
+    res
+  }
+
   pub fn blit(&self, dst: &Fbo, src_attachment: u32, dst_attachment: u32, filter: u32) {
     let src_mask = match src_attachment {
       gl::COLOR_ATTACHMENT0..=gl::COLOR_ATTACHMENT31 => gl::COLOR_BUFFER_BIT,
@@ -480,6 +755,16 @@ pub fn gl_draw_elements(cap: u32, len: i32) {
   unsafe { gl::DrawElements(cap, len, gl::UNSIGNED_INT, 0 as *const c_void) }
 }
 
+// start is in indices, not bytes
+pub fn gl_draw_elements_base_vertex(cap: u32, len: i32, start: usize, base_vertex: i32) {
+  unsafe { gl::DrawElementsBaseVertex(cap, len, gl::UNSIGNED_INT, (start * 4) as *const c_void, base_vertex) }
+}
+
+// reads `count` commands from the bound DRAW_INDIRECT_BUFFER, offset is in bytes
+pub fn gl_multi_draw_elements_indirect(cap: u32, offset: usize, count: i32) {
+  unsafe { gl::MultiDrawElementsIndirect(cap, gl::UNSIGNED_INT, offset as *const c_void, count, 0) }
+}
+
 pub fn gl_enable(cap: u32) {
   unsafe { gl::Enable(cap); }
 }
@@ -492,12 +777,16 @@ pub fn gl_disable(cap: u32) {
   unsafe { gl::Disable(cap); }
 }
 
-pub fn gl_gen_v(attribs: &[(i32, bool)]) -> (Vao, Buf) {
+pub fn gl_cull_face(mode: u32) {
+  unsafe { gl::CullFace(mode); }
+}
+
+pub fn gl_gen_v(attribs: &[Attrib]) -> (Vao, Buf) {
   let vao = Vao::new();
   let vbo = Buf::new(gl::ARRAY_BUFFER);
 
   unsafe {
-    gl::VertexArrayVertexBuffer(vao.0, 0, vbo.id, 0, attribs.iter().map(|(size, _)| size).sum::<i32>() * 4);
+    gl::VertexArrayVertexBuffer(vao.0, 0, vbo.id, 0, gl_stride(attribs));
 
     gl_select_attribs(attribs, &vao);
   }
@@ -505,13 +794,13 @@ pub fn gl_gen_v(attribs: &[(i32, bool)]) -> (Vao, Buf) {
   (vao, vbo)
 }
 
-pub fn gl_gen_vi(attribs: &[(i32, bool)]) -> (Vao, Buf, Buf) {
+pub fn gl_gen_vi(attribs: &[Attrib]) -> (Vao, Buf, Buf) {
   let vao = Vao::new();
   let vbo = Buf::new(gl::ARRAY_BUFFER);
   let ibo = Buf::new(gl::ELEMENT_ARRAY_BUFFER);
 
   unsafe {
-    gl::VertexArrayVertexBuffer(vao.0, 0, vbo.id, 0, attribs.iter().map(|(size, _)| size).sum::<i32>() * 4);
+    gl::VertexArrayVertexBuffer(vao.0, 0, vbo.id, 0, gl_stride(attribs));
     gl::VertexArrayElementBuffer(vao.0, ibo.id);
 
     gl_select_attribs(attribs, &vao);
@@ -520,13 +809,27 @@ pub fn gl_gen_vi(attribs: &[(i32, bool)]) -> (Vao, Buf, Buf) {
   (vao, vbo, ibo)
 }
 
-unsafe fn gl_select_attribs(attribs: &[(i32, bool)], vao: &Vao) {
+// points an existing vao at new buffers with the same layout
+pub fn gl_rebind_vi(vao: &Vao, vbo: &Buf, ibo: &Buf, attribs: &[Attrib]) {
+  unsafe {
+    gl::VertexArrayVertexBuffer(vao.0, 0, vbo.id, 0, gl_stride(attribs));
+    gl::VertexArrayElementBuffer(vao.0, ibo.id);
+  }
+}
+
+unsafe fn gl_select_attribs(attribs: &[Attrib], vao: &Vao) {
   let mut off = 0;
   for i in 0..attribs.len() {
-    gl::EnableVertexArrayAttrib(vao.0, i as u32);
-    gl::VertexArrayAttribFormat(vao.0, i as u32, attribs[i].0, if attribs[i].1 { gl::FLOAT } else { gl::INT }, gl::FALSE, off);
-    gl::VertexArrayAttribBinding(vao.0, i as u32, 0);
-    off += attribs[i].0 as u32 * 4;
+    let (size, type_, normalized) = attribs[i];
+    let loc = i as u32;
+    gl::EnableVertexArrayAttrib(vao.0, loc);
+    if type_ == gl::FLOAT || type_ == gl::HALF_FLOAT || normalized {
+      gl::VertexArrayAttribFormat(vao.0, loc, size, type_, normalized as u8, off);
+    } else {
+      gl::VertexArrayAttribIFormat(vao.0, loc, size, type_, off);
+    }
+    gl::VertexArrayAttribBinding(vao.0, loc, 0);
+    off += (size * gl_type_size(type_)) as u32;
   }
 }
 
@@ -690,4 +993,33 @@ impl FramebufferAttached for Window {
 
     unsafe { &FBO }.as_ref().unwrap()
   }
+}
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use super::read_shader;
+
+  #[test]
+  fn includes_are_pasted_in() {
+    let dir = std::env::temp_dir();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    fs::write(path("hana_shader_test.vert"), "#version 460 core\r\n#include \"hana_shader_test.glsl\"\r\nvoid main() {}").unwrap();
+    fs::write(path("hana_shader_test.glsl"), "  #include \"hana_shader_test_inner.glsl\"\nfloat b;").unwrap();
+    fs::write(path("hana_shader_test_inner.glsl"), "float a;").unwrap();
+    fs::write(path("hana_shader_test_loop.glsl"), "#include \"hana_shader_test_loop.glsl\"").unwrap();
+    fs::write(path("hana_shader_test_bad.glsl"), "float a;\n#include <nope>").unwrap();
+
+    let src = read_shader(&path("hana_shader_test.vert"), 0);
+    let looped = read_shader(&path("hana_shader_test_loop.glsl"), 0);
+    let bad = read_shader(&path("hana_shader_test_bad.glsl"), 0);
+    let missing = read_shader(&path("hana_shader_test_missing.glsl"), 0);
+    for it in ["hana_shader_test.vert", "hana_shader_test.glsl", "hana_shader_test_inner.glsl", "hana_shader_test_loop.glsl", "hana_shader_test_bad.glsl"] {
+      fs::remove_file(path(it)).unwrap();
+    }
+
+    assert_eq!(src.unwrap(), "#version 460 core\nfloat a;\n\n#line 2\nfloat b;\n\n#line 3\nvoid main() {}\n");
+    assert!(looped.unwrap_err().ends_with("includes nested too deep"));
+    assert!(bad.unwrap_err().ends_with("hana_shader_test_bad.glsl:2: expected #include \"file\""));
+    assert!(missing.unwrap_err().contains("hana_shader_test_missing.glsl"));
+  }
 }
\ No newline at end of file
@@ -0,0 +1,567 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use glam::Vec2;
+use glfw::{Action, GamepadAxis, GamepadButton, Glfw, JoystickId, Key, MouseButton, WindowEvent};
+
+// what can be bound to an action, or to either end of an axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+  Key(Key),
+  Mouse(MouseButton),
+  Pad(GamepadButton),
+  // a pad axis pushed more than half way, true towards positive. for triggers
+  PadAxis(GamepadAxis, bool)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+  // -1 while the first is held, 1 while the second is
+  Buttons(Button, Button),
+  Pad(GamepadAxis, f32),
+  // pixels moved since the last tick, x or y, times the scale
+  Mouse(bool, f32)
+}
+
+// every key but Key::Unknown, which is what glfw sends for keys it has no name for. bindings
+// files use these names, see Button::name
+const KEYS: &[Key] = &[
+  Key::Space, Key::Apostrophe, Key::Comma, Key::Minus, Key::Period, Key::Slash,
+  Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+  Key::Semicolon, Key::Equal,
+  Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+  Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+  Key::LeftBracket, Key::Backslash, Key::RightBracket, Key::GraveAccent, Key::World1, Key::World2,
+  Key::Escape, Key::Enter, Key::Tab, Key::Backspace, Key::Insert, Key::Delete,
+  Key::Right, Key::Left, Key::Down, Key::Up, Key::PageUp, Key::PageDown, Key::Home, Key::End,
+  Key::CapsLock, Key::ScrollLock, Key::NumLock, Key::PrintScreen, Key::Pause,
+  Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12, Key::F13,
+  Key::F14, Key::F15, Key::F16, Key::F17, Key::F18, Key::F19, Key::F20, Key::F21, Key::F22, Key::F23, Key::F24, Key::F25,
+  Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4, Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8, Key::Kp9,
+  Key::KpDecimal, Key::KpDivide, Key::KpMultiply, Key::KpSubtract, Key::KpAdd, Key::KpEnter, Key::KpEqual,
+  Key::LeftShift, Key::LeftControl, Key::LeftAlt, Key::LeftSuper,
+  Key::RightShift, Key::RightControl, Key::RightAlt, Key::RightSuper, Key::Menu
+];
+
+fn pad_buttons() -> impl Iterator<Item = GamepadButton> {
+  (0..).map_while(GamepadButton::from_i32)
+}
+
+fn pad_axes() -> impl Iterator<Item = GamepadAxis> {
+  (0..).map_while(GamepadAxis::from_i32)
+}
+
+// e.g. key:LeftShift, mouse:2, pad:A, pad:LeftTrigger+
+impl Button {
+  pub fn name(&self) -> String {
+    match self {
+      Button::Key(key) => format!("key:{:?}", key),
+      Button::Mouse(button) => format!("mouse:{}", *button as i32 + 1),
+      Button::Pad(button) => format!("pad:{}", format!("{:?}", button).trim_start_matches("Button")),
+      Button::PadAxis(axis, positive) => format!("pad:{}{}", axis_name(*axis), if *positive { "+" } else { "-" })
+    }
+  }
+
+  pub fn parse(name: &str) -> Option<Button> {
+    let (kind, name) = name.split_once(':')?;
+    match kind {
+      "key" => KEYS.iter().find(|it| format!("{:?}", it) == name).map(|it| Button::Key(*it)),
+      "mouse" => MouseButton::from_i32(name.parse::<i32>().ok()? - 1).map(Button::Mouse),
+      "pad" => {
+        if let Some(it) = pad_buttons().find(|it| Button::Pad(*it).name()[4..] == *name) {
+          return Some(Button::Pad(it));
+        }
+
+        let (axis, sign) = name.split_at(name.len().checked_sub(1)?);
+        let positive = match sign { "+" => true, "-" => false, _ => return None };
+        parse_axis(axis).map(|it| Button::PadAxis(it, positive))
+      }
+      _ => None
+    }
+  }
+}
+
+fn axis_name(axis: GamepadAxis) -> String {
+  format!("{:?}", axis).trim_start_matches("Axis").to_string()
+}
+
+// glfw's triggers rest at -1 and go to 1, they're 0..1 here like a half axis
+fn pad_axis_value(axis: GamepadAxis, raw: f32) -> f32 {
+  match axis {
+    GamepadAxis::AxisLeftTrigger | GamepadAxis::AxisRightTrigger => (raw + 1.) * 0.5,
+    _ => raw
+  }
+}
+
+fn parse_axis(name: &str) -> Option<GamepadAxis> {
+  pad_axes().find(|it| axis_name(*it) == name)
+}
+
+fn split_scale(token: &str) -> Option<(&str, f32)> {
+  match token.split_once('*') {
+    Some((name, scale)) => Some((name, scale.parse().ok()?)),
+    None => Some((token, 1.))
+  }
+}
+
+// e.g. key:A/key:D, pad_axis:LeftY*-1, mouse_x*0.5
+impl Source {
+  pub fn name(&self) -> String {
+    match self {
+      Source::Buttons(neg, pos) => format!("{}/{}", neg.name(), pos.name()),
+      Source::Pad(axis, scale) => format!("pad_axis:{}*{}", axis_name(*axis), scale),
+      Source::Mouse(y, scale) => format!("mouse_{}*{}", if *y { "y" } else { "x" }, scale)
+    }
+  }
+
+  pub fn parse(name: &str) -> Option<Source> {
+    if let Some((neg, pos)) = name.split_once('/') {
+      return Some(Source::Buttons(Button::parse(neg)?, Button::parse(pos)?));
+    }
+
+    let (name, scale) = split_scale(name)?;
+    match name {
+      "mouse_x" => Some(Source::Mouse(false, scale)),
+      "mouse_y" => Some(Source::Mouse(true, scale)),
+      _ => parse_axis(name.strip_prefix("pad_axis:")?).map(|it| Source::Pad(it, scale))
+    }
+  }
+}
+
+// named actions and axes, and what drives them
+#[derive(Clone, Debug)]
+pub struct Bindings {
+  pub actions: BTreeMap<String, Vec<Button>>,
+  pub axes: BTreeMap<String, Vec<Source>>,
+  // pad axes closer to rest than this read as 0
+  pub deadzone: f32
+}
+
+impl Bindings {
+  pub fn new() -> Bindings {
+    Bindings { actions: BTreeMap::new(), axes: BTreeMap::new(), deadzone: 0.15 }
+  }
+
+  pub fn bind(&mut self, action: &str, button: Button) {
+    let buttons = self.actions.entry(action.to_string()).or_default();
+    if !buttons.contains(&button) {
+      buttons.push(button);
+    }
+  }
+
+  pub fn bind_axis(&mut self, axis: &str, source: Source) {
+    self.axes.entry(axis.to_string()).or_default().push(source);
+  }
+
+  // for rebinding, clears the action or axis before binding something else to it
+  pub fn unbind(&mut self, name: &str) {
+    self.actions.remove(name);
+    self.axes.remove(name);
+  }
+
+  // plain text, `action <name> <button>..`, `axis <name> <source>..` and `deadzone <n>` lines
+  pub fn save(&self, path: &str) -> Result<(), String> {
+    let mut res = format!("# hana input bindings\ndeadzone {}\n", self.deadzone);
+    for (name, buttons) in &self.actions {
+      res += &format!("action {} {}\n", name, buttons.iter().map(Button::name).collect::<Vec<_>>().join(" "));
+    }
+
+    for (name, sources) in &self.axes {
+      res += &format!("axis {} {}\n", name, sources.iter().map(Source::name).collect::<Vec<_>>().join(" "));
+    }
+
+    fs::write(path, res).map_err(|e| path.to_string() + ": " + &e.to_string())
+  }
+
+  pub fn load(path: &str) -> Result<Bindings, String> {
+    let text = fs::read_to_string(path).map_err(|e| path.to_string() + ": " + &e.to_string())?;
+    let mut res = Bindings::new();
+    for (n, line) in text.lines().enumerate() {
+      let err = |what: &str| format!("{}:{}: {}", path, n + 1, what);
+      // replay::Recording writes frames as name=value and lists names with commas
+      let check = |name: &str| match name.contains([',', '=']) {
+        true => Err(err(&format!("{} can't have , or = in it", name))),
+        false => Ok(())
+      };
+
+      let words = line.split_whitespace().collect::<Vec<_>>();
+      match words.as_slice() {
+        [] => {}
+        [it, ..] if it.starts_with('#') => {}
+        ["deadzone", val] => res.deadzone = val.parse().map_err(|_| err("bad deadzone"))?,
+        ["action", name, buttons @ ..] => {
+          check(name)?;
+          res.actions.entry(name.to_string()).or_default();
+          for it in buttons {
+            res.bind(name, Button::parse(it).ok_or_else(|| err(&format!("unknown button {}", it)))?);
+          }
+        }
+        ["axis", name, sources @ ..] => {
+          check(name)?;
+          res.axes.entry(name.to_string()).or_default();
+          for it in sources {
+            res.bind_axis(name, Source::parse(it).ok_or_else(|| err(&format!("unknown axis source {}", it)))?);
+          }
+        }
+        _ => return Err(err("expected `action`, `axis` or `deadzone`"))
+      }
+    }
+
+    Ok(res)
+  }
+}
+
+impl Default for Bindings {
+  // what main plays with
+  fn default() -> Self {
+    let mut res = Bindings::new();
+    let key = |it: &str| Button::parse(&("key:".to_string() + it)).unwrap();
+    for (axis, neg, pos, pad) in [
+      ("move_x", "A", "D", "pad_axis:LeftX"),
+      ("move_y", "LeftShift", "Space", "pad:LeftTrigger+/pad:RightTrigger+"),
+      ("move_z", "S", "W", "pad_axis:LeftY*-1")
+    ] {
+      res.bind_axis(axis, Source::Buttons(key(neg), key(pos)));
+      res.bind_axis(axis, Source::parse(pad).unwrap());
+    }
+
+    res.bind_axis("look_x", Source::Mouse(false, 1.));
+    res.bind_axis("look_x", Source::Pad(GamepadAxis::AxisRightX, 20.));
+    res.bind_axis("look_y", Source::Mouse(true, 1.));
+    res.bind_axis("look_y", Source::Pad(GamepadAxis::AxisRightY, 20.));
+
+    for (action, buttons) in [
+      ("sprint", &["key:LeftControl", "pad:LeftThumb"][..]),
+      ("orbit", &["key:C", "pad:Y"]),
+      ("pick", &["mouse:2", "pad:RightBumper"]),
+      ("carry", &["key:F", "pad:X"]),
+      ("projection", &["key:O"]),
+      ("record_key", &["key:K"]),
+      ("play_path", &["key:P"]),
+      ("save_path", &["key:F5"]),
+      ("load_path", &["key:F9"]),
+      ("start", &["key:Enter", "pad:Start"]),
+      ("pause", &["key:F6"]),
+      ("step", &["key:F7"]),
+      ("rebind", &["key:F8"])
+    ] {
+      for it in buttons {
+        res.bind(action, Button::parse(it).unwrap());
+      }
+    }
+
+    res
+  }
+}
+
+// what the bindings read as during one tick, by action and axis name. see replay::Recording
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+  pub held: BTreeSet<String>,
+  pub pressed: BTreeSet<String>,
+  pub released: BTreeSet<String>,
+  // axes at 0 are left out
+  pub axes: BTreeMap<String, f32>
+}
+
+// what's held down, read through Bindings. fed by window events and the gamepad, or by hand
+// with press / release / mouse_move / set_pad_axis when there's no window, e.g. in tests
+pub struct Input {
+  pub bindings: Bindings,
+  down: HashSet<Button>,
+  // since the last tick
+  pressed: HashSet<Button>,
+  released: HashSet<Button>,
+  mouse: Vec2,
+  pad: [f32; 6],
+  last: Option<Button>,
+  // read instead of any of the above while replaying
+  frame: Option<Frame>
+}
+
+impl Input {
+  pub fn new(bindings: Bindings) -> Input {
+    Input {
+      bindings,
+      down: HashSet::new(),
+      pressed: HashSet::new(),
+      released: HashSet::new(),
+      mouse: Vec2::ZERO,
+      pad: [0.; 6],
+      last: None,
+      frame: None
+    }
+  }
+
+  pub fn press(&mut self, button: Button) {
+    if self.down.insert(button) {
+      self.pressed.insert(button);
+      self.last = Some(button);
+    }
+  }
+
+  pub fn release(&mut self, button: Button) {
+    if self.down.remove(&button) {
+      self.released.insert(button);
+    }
+  }
+
+  fn set(&mut self, button: Button, down: bool) {
+    if down { self.press(button) } else { self.release(button) }
+  }
+
+  pub fn mouse_move(&mut self, x_off: f32, y_off: f32) {
+    self.mouse += Vec2::new(x_off, y_off);
+  }
+
+  // -1..1, sticks are y down like glfw reports them
+  pub fn set_pad_axis(&mut self, axis: GamepadAxis, val: f32) {
+    self.pad[axis as usize] = val;
+    self.set(Button::PadAxis(axis, true), val > 0.5);
+    self.set(Button::PadAxis(axis, false), val < -0.5);
+  }
+
+  // keys and mouse buttons, cursor movement is up to the caller since it depends on the cursor mode
+  pub fn event(&mut self, event: &WindowEvent) {
+    match *event {
+      WindowEvent::Key(key, _, Action::Press, _) => self.press(Button::Key(key)),
+      WindowEvent::Key(key, _, Action::Release, _) => self.release(Button::Key(key)),
+      WindowEvent::MouseButton(button, Action::Press, _) => self.press(Button::Mouse(button)),
+      WindowEvent::MouseButton(button, Action::Release, _) => self.release(Button::Mouse(button)),
+      _ => {}
+    }
+  }
+
+  // the first connected gamepad, if any
+  pub fn poll_gamepad(&mut self, glfw: &Glfw) {
+    let state = (0..).map_while(JoystickId::from_i32)
+      .map(|it| glfw.get_joystick(it))
+      .find(|it| it.is_gamepad())
+      .and_then(|it| it.get_gamepad_state());
+    for it in pad_buttons() {
+      self.set(Button::Pad(it), state.is_some_and(|state| state.get_button_state(it) == Action::Press));
+    }
+
+    for it in pad_axes() {
+      self.set_pad_axis(it, state.map_or(0., |state| pad_axis_value(it, state.get_axis(it))));
+    }
+  }
+
+  // call at the end of every tick
+  pub fn tick(&mut self) {
+    self.pressed.clear();
+    self.released.clear();
+    self.mouse = Vec2::ZERO;
+  }
+
+  fn any(&self, action: &str, set: &HashSet<Button>) -> bool {
+    self.bindings.actions.get(action).is_some_and(|it| it.iter().any(|it| set.contains(it)))
+  }
+
+  pub fn held(&self, action: &str) -> bool {
+    match &self.frame {
+      Some(frame) => frame.held.contains(action),
+      None => self.any(action, &self.down)
+    }
+  }
+
+  // went down since the last tick
+  pub fn pressed(&self, action: &str) -> bool {
+    match &self.frame {
+      Some(frame) => frame.pressed.contains(action),
+      None => self.any(action, &self.pressed)
+    }
+  }
+
+  pub fn released(&self, action: &str) -> bool {
+    match &self.frame {
+      Some(frame) => frame.released.contains(action),
+      None => self.any(action, &self.released)
+    }
+  }
+
+  // buttons and pad axes add up to at most -1..1, mouse movement goes on top unclamped
+  pub fn axis(&self, axis: &str) -> f32 {
+    if let Some(frame) = &self.frame {
+      return frame.axes.get(axis).copied().unwrap_or(0.);
+    }
+
+    let (mut res, mut mouse) = (0., 0.);
+    for it in self.bindings.axes.get(axis).into_iter().flatten() {
+      match *it {
+        Source::Buttons(neg, pos) => res += self.down.contains(&pos) as i32 as f32 - self.down.contains(&neg) as i32 as f32,
+        Source::Pad(axis, scale) => {
+          let val = self.pad[axis as usize];
+          if val.abs() > self.bindings.deadzone {
+            res += val * scale;
+          }
+        }
+        Source::Mouse(y, scale) => mouse += if y { self.mouse.y } else { self.mouse.x } * scale
+      }
+    }
+
+    res.clamp(-1., 1.) + mouse
+  }
+
+  // pressed since the last tick, and then not again until it goes down again. for actions outside
+  // the fixed tick like pausing it, so it only ever looks at the devices
+  pub fn take(&mut self, action: &str) -> bool {
+    let Some(buttons) = self.bindings.actions.get(action) else {
+      return false;
+    };
+
+    buttons.iter().fold(false, |res, it| self.pressed.remove(it) | res)
+  }
+
+  // every bound action and axis as they read this tick, take it before Input::tick
+  pub fn frame(&self) -> Frame {
+    if let Some(frame) = &self.frame {
+      return frame.clone();
+    }
+
+    let names = |set| self.bindings.actions.keys().filter(|it| self.any(it, set)).cloned().collect();
+    Frame {
+      held: names(&self.down),
+      pressed: names(&self.pressed),
+      released: names(&self.released),
+      axes: self.bindings.axes.keys().map(|it| (it.clone(), self.axis(it))).filter(|it| it.1 != 0.).collect()
+    }
+  }
+
+  // reads come from `frame` instead of the devices until this is called with None
+  pub fn play(&mut self, frame: Option<Frame>) {
+    self.frame = frame;
+  }
+
+  // the last button to go down, for rebinding to whatever gets pressed next. never Key::Unknown,
+  // it couldn't be saved
+  pub fn take_last(&mut self) -> Option<Button> {
+    self.last.take().filter(|it| *it != Button::Key(Key::Unknown))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use glfw::{GamepadAxis, Key, MouseButton};
+  use super::{pad_axis_value, Bindings, Button, Input, Source};
+
+  #[test]
+  fn names_round_trip() {
+    for it in [
+      Button::Key(Key::LeftShift),
+      Button::Key(Key::Num1),
+      Button::Mouse(MouseButton::Button2),
+      Button::Pad(glfw::GamepadButton::ButtonDpadUp),
+      Button::PadAxis(GamepadAxis::AxisLeftTrigger, true)
+    ] {
+      assert_eq!(Button::parse(&it.name()), Some(it));
+    }
+
+    for it in [Source::Pad(GamepadAxis::AxisLeftY, -1.), Source::Mouse(true, 0.5)] {
+      assert_eq!(Source::parse(&it.name()), Some(it));
+    }
+
+    for it in super::KEYS {
+      assert_eq!(Button::parse(&Button::Key(*it).name()), Some(Button::Key(*it)));
+    }
+
+    assert_eq!(Button::parse("key:Nope"), None);
+    assert_eq!(Button::parse("key:Unknown"), None);
+  }
+
+  #[test]
+  fn actions_and_axes_from_synthetic_input() {
+    let mut input = Input::new(Bindings::default());
+    input.press(Button::Key(Key::W));
+    input.press(Button::Key(Key::LeftControl));
+    input.set_pad_axis(GamepadAxis::AxisLeftY, -1.);
+    input.mouse_move(3., 0.);
+    assert_eq!(input.axis("move_z"), 1.);
+    assert_eq!(input.axis("look_x"), 3.);
+    assert!(input.held("sprint") && input.pressed("sprint"));
+
+    input.tick();
+    input.release(Button::Key(Key::LeftControl));
+    input.set_pad_axis(GamepadAxis::AxisLeftY, -0.1);
+    assert!(!input.pressed("sprint") && input.released("sprint"));
+    assert_eq!(input.axis("look_x"), 0.);
+    assert_eq!(input.axis("move_z"), 1.);
+    assert_eq!(input.axis("nothing"), 0.);
+  }
+
+  #[test]
+  fn triggers_rest_at_zero() {
+    assert_eq!(pad_axis_value(GamepadAxis::AxisLeftTrigger, -1.), 0.);
+    assert_eq!(pad_axis_value(GamepadAxis::AxisRightTrigger, 1.), 1.);
+    assert_eq!(pad_axis_value(GamepadAxis::AxisLeftX, -1.), -1.);
+
+    let mut input = Input::new(Bindings::default());
+    for it in [GamepadAxis::AxisLeftTrigger, GamepadAxis::AxisRightTrigger] {
+      input.set_pad_axis(it, pad_axis_value(it, -1.));
+    }
+
+    assert_eq!(input.axis("move_y"), 0.);
+    input.set_pad_axis(GamepadAxis::AxisRightTrigger, pad_axis_value(GamepadAxis::AxisRightTrigger, 0.5));
+    assert_eq!(input.axis("move_y"), 1.);
+  }
+
+  #[test]
+  fn rebinding() {
+    let mut input = Input::new(Bindings::default());
+    input.press(Button::Key(Key::G));
+    let button = input.take_last().unwrap();
+    input.bindings.unbind("sprint");
+    input.bindings.bind("sprint", button);
+    assert!(input.held("sprint"));
+    assert_eq!(input.take_last(), None);
+    input.press(Button::Key(Key::Unknown));
+    assert_eq!(input.take_last(), None);
+    assert!(input.take("sprint") && !input.take("sprint"));
+    assert!(!input.pressed("sprint") && input.held("sprint"));
+  }
+
+  #[test]
+  fn frames_play_back_the_same() {
+    let mut input = Input::new(Bindings::default());
+    input.press(Button::Key(Key::D));
+    input.press(Button::Key(Key::C));
+    input.mouse_move(-2., 0.5);
+    let frame = input.frame();
+    assert!(frame.pressed.contains("orbit") && frame.held.contains("orbit"));
+
+    let mut replay = Input::new(Bindings::new());
+    replay.play(Some(frame));
+    for it in ["move_x", "move_z", "look_x", "look_y"] {
+      assert_eq!(replay.axis(it), input.axis(it));
+    }
+
+    assert!(replay.pressed("orbit") && !replay.held("sprint"));
+  }
+
+  #[test]
+  fn save_and_load() {
+    let path = std::env::temp_dir().join("hana_bindings_test.cfg");
+    let path = path.to_str().unwrap();
+    let bindings = Bindings::default();
+    bindings.save(path).unwrap();
+    let loaded = Bindings::load(path).unwrap();
+    assert_eq!(loaded.actions, bindings.actions);
+    assert_eq!(loaded.axes, bindings.axes);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn load_rejects_names_replays_cant_hold() {
+    let path = std::env::temp_dir().join("hana_bindings_names_test.cfg");
+    let path = path.to_str().unwrap();
+    let mut res = Vec::new();
+    for it in ["action jump,dash key:Space", "axis look=x mouse_x", "action jump key:Space"] {
+      std::fs::write(path, it).unwrap();
+      res.push(Bindings::load(path).err());
+    }
+
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(res[0], Some(format!("{}:1: jump,dash can't have , or = in it", path)));
+    assert_eq!(res[1], Some(format!("{}:1: look=x can't have , or = in it", path)));
+    assert_eq!(res[2], None);
+  }
+}
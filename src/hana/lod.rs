@@ -0,0 +1,363 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use glam::{DVec3, Vec3};
+use crate::hana::model::Vertex;
+
+// fractions of the full triangle count, each level is simplified from the one before it
+pub const LOD_RATIOS: [f32; 3] = [0.5, 0.25, 0.125];
+
+// level i is drawn while the mesh covers at least LOD_COVERAGE[i] of the screen height,
+// anything smaller gets the last level
+pub const LOD_COVERAGE: [f32; 3] = [0.3, 0.12, 0.05];
+
+// not worth the extra indices below this
+const LOD_MIN_TRIANGLES: usize = 64;
+
+// a range of Mesh::indices, level 0 is the full mesh
+#[derive(Clone, Copy, Debug)]
+pub struct Lod {
+  pub start: u32,
+  pub count: u32
+}
+
+impl Lod {
+  pub fn full(count: usize) -> Lod {
+    Lod { start: 0, count: count as u32 }
+  }
+}
+
+// returns every level's indices back to back, along with where each level lives
+pub fn generate(vertices: &[Vertex], indices: &[u32]) -> (Vec<u32>, Vec<Lod>) {
+  let mut all = indices.to_vec();
+  let mut lods = vec![Lod::full(indices.len())];
+  if indices.len() / 3 < LOD_MIN_TRIANGLES {
+    return (all, lods);
+  }
+
+  let mut prev = indices.to_vec();
+  for ratio in LOD_RATIOS {
+    let target = ((indices.len() / 3) as f32 * ratio) as usize;
+    let next = simplify(vertices, &prev, target);
+    // stuck on locked borders, the next levels would only be copies
+    if next.is_empty() || next.len() as f32 > prev.len() as f32 * 0.9 {
+      break;
+    }
+
+    lods.push(Lod { start: all.len() as u32, count: next.len() as u32 });
+    all.extend_from_slice(&next);
+    prev = next;
+  }
+
+  (all, lods)
+}
+
+pub fn level(coverage: f32) -> usize {
+  LOD_COVERAGE.iter().take_while(|it| coverage < **it).count()
+}
+
+// meshes with fewer levels use their last one
+pub fn select(lods: &[Lod], level: usize) -> Lod {
+  lods[level.min(lods.len() - 1)]
+}
+
+// symmetric 4x4, a2 ab ac ad b2 bc bd c2 cd d2
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+  fn plane(norm: DVec3, d: f64) -> Quadric {
+    let (a, b, c) = (norm.x, norm.y, norm.z);
+    Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d])
+  }
+
+  fn add(&self, other: &Quadric) -> Quadric {
+    let mut res = *self;
+    for (it, other) in res.0.iter_mut().zip(other.0) {
+      *it += other;
+    }
+
+    res
+  }
+
+  // sum of squared distances to every plane folded into this quadric
+  fn error(&self, pos: Vec3) -> f64 {
+    let (x, y, z) = (pos.x as f64, pos.y as f64, pos.z as f64);
+    let q = &self.0;
+    q[0] * x * x + 2. * q[1] * x * y + 2. * q[2] * x * z + 2. * q[3] * x
+      + q[4] * y * y + 2. * q[5] * y * z + 2. * q[6] * y
+      + q[7] * z * z + 2. * q[8] * z
+      + q[9]
+  }
+}
+
+// moves `from` onto `to`, the heap pops the cheapest first
+struct Collapse {
+  cost: f64,
+  from: usize,
+  to: usize
+}
+
+impl PartialEq for Collapse {
+  fn eq(&self, other: &Self) -> bool {
+    self.cost == other.cost
+  }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Collapse {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other.cost.total_cmp(&self.cost)
+  }
+}
+
+fn root(parent: &[usize], mut i: usize) -> usize {
+  while parent[i] != i {
+    i = parent[i];
+  }
+
+  i
+}
+
+fn tri_norm(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+  (b - a).cross(c - a)
+}
+
+// garland & heckbert edge collapse down to roughly `target` triangles. vertices are welded by
+// position first so split normals and uvs don't read as open borders, actual open borders are
+// locked so the silhouette and seams between meshes stay put. the result indexes the same vertices
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target: usize) -> Vec<u32> {
+  let mut group = vec![0; vertices.len()];
+  let mut members: Vec<Vec<u32>> = Vec::new();
+  let mut by_pos = HashMap::new();
+  for (i, it) in vertices.iter().enumerate() {
+    let key = { it.pos }.to_array().map(f32::to_bits);
+    let g = *by_pos.entry(key).or_insert_with(|| {
+      members.push(Vec::new());
+      members.len() - 1
+    });
+
+    group[i] = g;
+    members[g].push(i as u32);
+  }
+
+  let pos = members.iter().map(|it| vertices[it[0] as usize].pos).collect::<Vec<_>>();
+  let mut tris = indices.chunks_exact(3).map(|it| [0, 1, 2].map(|i| group[it[i] as usize])).collect::<Vec<_>>();
+
+  let mut quadrics = vec![Quadric::default(); pos.len()];
+  let mut adj = vec![Vec::new(); pos.len()];
+  let mut edges = HashMap::new();
+  let mut removed = vec![false; tris.len()];
+  let mut live = 0;
+  for (t, tri) in tris.iter().enumerate() {
+    let [a, b, c] = *tri;
+    if a == b || b == c || c == a {
+      removed[t] = true;
+      continue;
+    }
+
+    let norm = tri_norm(pos[a], pos[b], pos[c]);
+    if norm.length_squared() > 0. {
+      let norm = norm.normalize().as_dvec3();
+      let plane = Quadric::plane(norm, -norm.dot(pos[a].as_dvec3()));
+      for it in tri {
+        quadrics[*it] = quadrics[*it].add(&plane);
+      }
+    }
+
+    for (u, v) in [(a, b), (b, c), (c, a)] {
+      *edges.entry((u.min(v), u.max(v))).or_insert(0) += 1;
+    }
+
+    for it in tri {
+      adj[*it].push(t);
+    }
+
+    live += 1;
+  }
+
+  // borders and non-manifold edges
+  let mut locked = vec![false; pos.len()];
+  for ((u, v), count) in &edges {
+    if *count != 2 {
+      locked[*u] = true;
+      locked[*v] = true;
+    }
+  }
+
+  let cost = |quadrics: &[Quadric], u: usize, v: usize| -> Option<Collapse> {
+    let q = quadrics[u].add(&quadrics[v]);
+    match (locked[u], locked[v]) {
+      (true, true) => None,
+      (true, false) => Some(Collapse { cost: q.error(pos[u]), from: v, to: u }),
+      (false, true) => Some(Collapse { cost: q.error(pos[v]), from: u, to: v }),
+      (false, false) => {
+        let (to_u, to_v) = (q.error(pos[u]), q.error(pos[v]));
+        Some(if to_u < to_v { Collapse { cost: to_u, from: v, to: u } } else { Collapse { cost: to_v, from: u, to: v } })
+      }
+    }
+  };
+
+  let mut heap = edges.keys().filter_map(|(u, v)| cost(&quadrics, *u, *v)).collect::<BinaryHeap<_>>();
+  let mut parent = (0..pos.len()).collect::<Vec<_>>();
+  while live > target {
+    let Some(popped) = heap.pop() else {
+      break;
+    };
+
+    let (u, v) = (root(&parent, popped.from), root(&parent, popped.to));
+    if u == v {
+      continue;
+    }
+
+    let Some(it) = cost(&quadrics, u, v) else {
+      continue;
+    };
+
+    // one of the ends has taken on more error since this was queued
+    if it.cost > popped.cost * (1. + 1e-6) + 1e-12 {
+      heap.push(it);
+      continue;
+    }
+
+    let (from, to) = (it.from, it.to);
+    let flips = adj[from].iter().any(|t| {
+      let tri = tris[*t];
+      if removed[*t] || tri.contains(&to) {
+        return false;
+      }
+
+      let before = tri.map(|it| pos[it]);
+      let after = tri.map(|it| if it == from { pos[to] } else { pos[it] });
+      tri_norm(after[0], after[1], after[2]).dot(tri_norm(before[0], before[1], before[2])) <= 0.
+    });
+
+    if flips {
+      continue;
+    }
+
+    parent[from] = to;
+    quadrics[to] = quadrics[to].add(&quadrics[from]);
+    for t in std::mem::take(&mut adj[from]) {
+      if removed[t] {
+        continue;
+      }
+
+      let tri = tris[t].map(|it| if it == from { to } else { it });
+      tris[t] = tri;
+      if tri[0] == tri[1] || tri[1] == tri[2] || tri[2] == tri[0] {
+        removed[t] = true;
+        live -= 1;
+      } else {
+        adj[to].push(t);
+      }
+    }
+
+    adj[to].retain(|it| !removed[*it]);
+    let mut around = adj[to].iter().flat_map(|it| tris[*it]).filter(|it| *it != to).collect::<Vec<_>>();
+    around.sort_unstable();
+    around.dedup();
+    for it in around {
+      if let Some(it) = cost(&quadrics, to, it) {
+        heap.push(it);
+      }
+    }
+  }
+
+  // collapsed vertices take the attributes of whichever survivor at their new position looks most like them
+  let remap = (0..vertices.len()).map(|i| {
+    let g = root(&parent, group[i]);
+    if g == group[i] {
+      return i as u32;
+    }
+
+    let norm = vertices[i].norm;
+    let tint = vertices[i].tint;
+    let score = |it: &u32| {
+      let other = &vertices[*it as usize];
+      { other.norm }.dot(norm) + if other.tint == tint { 2. } else { 0. }
+    };
+
+    *members[g].iter().max_by(|a, b| score(a).total_cmp(&score(b))).unwrap()
+  }).collect::<Vec<_>>();
+
+  let mut res = Vec::with_capacity(live * 3);
+  for (t, it) in indices.chunks_exact(3).enumerate() {
+    if !removed[t] {
+      res.extend(it.iter().map(|it| remap[*it as usize]));
+    }
+  }
+
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use glam::Vec3;
+  use crate::hana::model::Vertex;
+  use super::{generate, level, select, simplify, Lod, LOD_COVERAGE};
+
+  // n by n quads on y = 0
+  fn grid(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let vertices = (0..=n).flat_map(|z| (0..=n).map(move |x| Vertex { pos: Vec3::new(x as f32, 0., z as f32), norm: Vec3::Y, ..Vertex::empty() }));
+    let mut indices = Vec::new();
+    for z in 0..n {
+      for x in 0..n {
+        let i = z * (n + 1) + x;
+        indices.extend([i, i + n + 1, i + 1, i + 1, i + n + 1, i + n + 2]);
+      }
+    }
+
+    (vertices.collect(), indices)
+  }
+
+  #[test]
+  fn levels_get_smaller() {
+    let (vertices, indices) = grid(16);
+    let (all, lods) = generate(&vertices, &indices);
+    assert!(lods.len() > 1);
+    assert_eq!(lods[0].count as usize, indices.len());
+    for pair in lods.windows(2) {
+      assert!(pair[1].count < pair[0].count);
+      assert_eq!(pair[1].start, pair[0].start + pair[0].count);
+    }
+
+    assert_eq!(all.len(), lods.iter().map(|it| it.count as usize).sum::<usize>());
+    assert!(all.iter().all(|it| (*it as usize) < vertices.len()));
+  }
+
+  #[test]
+  fn flat_grid_keeps_its_border() {
+    let (vertices, indices) = grid(8);
+    let res = simplify(&vertices, &indices, 0);
+    assert!(res.len() < indices.len());
+    assert_eq!(res.len() % 3, 0);
+    // the corners are on the locked border, so they're still used
+    for corner in [0, 8, 72, 80] {
+      assert!(res.contains(&corner));
+    }
+  }
+
+  #[test]
+  fn small_meshes_have_one_level() {
+    let (vertices, indices) = grid(2);
+    let (all, lods) = generate(&vertices, &indices);
+    assert_eq!((all, lods.len()), (indices, 1));
+  }
+
+  #[test]
+  fn levels_by_coverage() {
+    assert_eq!(level(1.), 0);
+    assert_eq!(level(LOD_COVERAGE[0] - 0.01), 1);
+    assert_eq!(level(0.), LOD_COVERAGE.len());
+
+    let lods = [Lod::full(30), Lod { start: 30, count: 12 }];
+    assert_eq!(select(&lods, 3).start, 30);
+  }
+}
@@ -1,59 +1,328 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
 use std::iter::zip;
 use std::ops::Deref;
-use glam::{Vec3, Vec4};
+use std::path::Path;
+use glam::{IVec4, Mat4, Vec2, Vec3, Vec4};
+use russimp::material::{Material as AiMaterial, PropertyTypeInfo, TextureType};
 use russimp::scene::{PostProcess, Scene};
+use crate::hana::anim::{Bone, Channel, Clip};
+use crate::hana::batch::{Command, DrawData, DrawGroups, Instance};
+use crate::hana::bounds::{Aabb, CullStats};
+use crate::hana::cache;
+use crate::hana::camera::View;
 use crate::hana::cvt::ScuffedInto;
-use crate::hana::glu::{Buf, FLOAT_3, FLOAT_4, gl_gen_vi, Vao};
+use crate::hana::glu::{Attrib, Buf, FLOAT_2, FLOAT_3, FLOAT_4, gl_cull_face, gl_disable, gl_draw_elements_base_vertex, gl_enable, INT_1, INT_4, Shader, Tex, TexSpec};
+use crate::hana::lod;
+use crate::hana::lod::Lod;
+use crate::hana::normals;
+use crate::hana::opt;
+use crate::hana::palette::Palette;
+use crate::hana::pool;
+use crate::hana::pool::{Format, Slot};
+use crate::hana::ray::{Bvh, Hit, Ray};
+use crate::hana::transform::{norm_mat, Transform};
 
 #[repr(packed(4))]
 #[derive(Clone)]
 pub struct Vertex {
   pub pos: Vec3,
   pub norm: Vec3,
-  pub tint: Vec4,
-  // tex: Vec2,
+  // palette ramp, -1 falls back to the shader's tint uniform
+  pub tint: i32,
+  pub tex: Vec2,
+  // up to 4 influences into Model::bones, unused slots have a weight of 0
+  pub bones: IVec4,
+  pub weights: Vec4,
 }
 
+pub const ATTRIBS: [Attrib; 6] = [FLOAT_3, FLOAT_3, INT_1, FLOAT_2, INT_4, FLOAT_4];
+
 impl Vertex {
   pub fn empty() -> Vertex {
     Vertex {
       pos: Vec3::ZERO,
       norm: Vec3::ZERO,
-      tint: Vec4::ONE
+      tint: -1,
+      tex: Vec2::ZERO,
+      bones: IVec4::ZERO,
+      weights: Vec4::ZERO
     }
   }
 }
 
+// a blend shape, offsets from the base mesh for every vertex
+pub struct Morph {
+  pub name: String,
+  pub pos: Vec<Vec3>,
+  pub norm: Vec<Vec3>,
+  // into Model::morphs, set by Model::assemble
+  pub slot: usize
+}
+
+// keep in sync with morph.glsl
+pub const MAX_MORPHS: usize = 32;
+pub const MORPH_BINDING: u32 = 1;
+// keep in sync with model.vert
+pub const OUTLINE_BINDING: u32 = 2;
+
 pub struct Mesh {
   pub vertices: Vec<Vertex>,
+  // every lod level back to back
   pub indices: Vec<u32>,
-  pub gl: (Vao, Buf, Buf)
+  pub lods: Vec<Lod>,
+  pub bounds: Aabb,
+  // into Model::materials
+  pub material: usize,
+  // skinned vertices are already in model space after skinning, so the node transform is skipped
+  pub skinned: bool,
+  // the gpu copy is opt::PackedVertex, vertices stays full precision
+  pub packed: bool,
+  // in pool::with(Format::of(packed))
+  pub slot: Slot,
+  // one per vertex, welded across hard edges and seams for inverted hull outlines.
+  // empty unless imported with ImportOptions::outline_normals, see set_outline
+  pub outline: Vec<Vec3>,
+  // see set_morphs
+  pub morphs: Vec<Morph>,
+  // every morph's deltas as (pos, 0) (norm, 0) pairs, target by target
+  morph_buf: Option<Buf>,
+  // outline as (norm, 0) per vertex
+  outline_buf: Option<Buf>,
+  // over the full detail level, built on the first raycast
+  bvh: OnceCell<Bvh>
 }
 
 impl Mesh {
   pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Mesh {
-    let res = Mesh {
+    let bounds = Aabb::from_points(vertices.iter().map(|it| it.pos));
+    Self::with_bounds(vertices, indices, bounds)
+  }
+
+  pub fn with_bounds(vertices: Vec<Vertex>, indices: Vec<u32>, bounds: Aabb) -> Mesh {
+    Mesh {
+      bounds,
+      lods: vec![Lod::full(indices.len())],
+      slot: pool::with(Format::Full, |it| it.alloc(&vertices, &indices)),
       vertices,
       indices,
-      gl: gl_gen_vi(&[FLOAT_3, FLOAT_3, FLOAT_4])
+      material: 0,
+      skinned: false,
+      packed: false,
+      outline: Vec::new(),
+      morphs: Vec::new(),
+      morph_buf: None,
+      outline_buf: None,
+      bvh: OnceCell::new()
+    }
+  }
+
+  // dedups, reorders for the vertex cache / overdraw / fetch and generates lods if asked to
+  pub fn optimized(vertices: Vec<Vertex>, indices: Vec<u32>, lods: bool) -> Mesh {
+    Self::optimized_morphs(vertices, indices, lods, Vec::new())
+  }
+
+  // optimized, with `morphs` following their vertices around. vertices only get merged
+  // when their deltas match as well
+  pub fn optimized_morphs(vertices: Vec<Vertex>, indices: Vec<u32>, lods: bool, morphs: Vec<Morph>) -> Mesh {
+    let extra = match morphs.is_empty() {
+      true => Vec::new(),
+      false => (0..vertices.len()).map(|i| {
+        morphs.iter().flat_map(|it| [it.pos[i], it.norm[i]]).flat_map(|it| it.to_array()).flat_map(f32::to_le_bytes).collect()
+      }).collect()
     };
 
-    let (_, vbo, ibo) = &res.gl;
+    let (vertices, indices, deduped) = opt::dedup(&vertices, &indices, &extra);
+    let indices = opt::optimize_overdraw(&vertices, &opt::optimize_cache(&indices, vertices.len()));
+    let (mut indices, lods) = if lods {
+      lod::generate(&vertices, &indices)
+    } else {
+      let lods = vec![Lod::full(indices.len())];
+      (indices, lods)
+    };
 
-    vbo.data(gl::DYNAMIC_DRAW, res.vertices.as_slice());
-    ibo.data(gl::DYNAMIC_DRAW, res.indices.as_slice());
+    for it in &lods[1..] {
+      let range = it.start as usize..(it.start + it.count) as usize;
+      let ordered = opt::optimize_cache(&indices[range.clone()], vertices.len());
+      indices[range].copy_from_slice(&ordered);
+    }
+
+    let (vertices, fetched) = opt::optimize_fetch(&vertices, &mut indices);
+    let mut res = Mesh::new(vertices, indices);
+    res.lods = lods;
+    if !morphs.is_empty() {
+      let origin = fetched.iter().map(|it| deduped[*it as usize] as usize).collect::<Vec<_>>();
+      res.set_morphs(morphs.into_iter().map(|it| Morph {
+        pos: origin.iter().map(|i| it.pos[*i]).collect(),
+        norm: origin.iter().map(|i| it.norm[*i]).collect(),
+        ..it
+      }).collect());
+    }
 
     res
   }
+
+  // uploads the deltas and grows the bounds to fit every target at full weight
+  pub fn set_morphs(&mut self, morphs: Vec<Morph>) {
+    let mut deltas = Vec::with_capacity(morphs.len() * self.vertices.len() * 2);
+    for it in &morphs {
+      deltas.extend(it.pos.iter().zip(&it.norm).flat_map(|(pos, norm)| [pos.extend(0.), norm.extend(0.)]));
+      let targets = self.vertices.iter().zip(&it.pos).map(|(vertex, delta)| vertex.pos + *delta);
+      self.bounds = self.bounds.union(&Aabb::from_points(targets));
+    }
+
+    self.morph_buf = match deltas.is_empty() {
+      true => None,
+      false => {
+        let buf = Buf::new(gl::SHADER_STORAGE_BUFFER);
+        buf.data(gl::STATIC_DRAW, &deltas);
+        Some(buf)
+      }
+    };
+
+    self.morphs = morphs;
+  }
+
+  // uploads the outline normals for draw_outline
+  pub fn set_outline(&mut self, outline: Vec<Vec3>) {
+    self.outline_buf = match outline.is_empty() {
+      true => None,
+      false => {
+        let buf = Buf::new(gl::SHADER_STORAGE_BUFFER);
+        buf.data(gl::STATIC_DRAW, &outline.iter().map(|it| it.extend(0.)).collect::<Vec<_>>());
+        Some(buf)
+      }
+    };
+
+    self.outline = outline;
+  }
+
+  // moves the mesh over to the packed pool
+  pub fn pack(&mut self) {
+    if self.packed {
+      return;
+    }
+
+    pool::with(Format::Full, |it| it.free(&self.slot));
+    self.slot = pool::with(Format::Packed, |it| it.alloc(&opt::pack(&self.vertices, &self.bounds), &self.indices));
+    self.packed = true;
+  }
+
+  pub fn format(&self) -> Format {
+    Format::of(self.packed)
+  }
+
+  // in mesh space, skinned meshes are hit in their bind pose and morphs are ignored
+  pub fn raycast(&self, ray: &Ray, max_t: f32) -> Option<Hit> {
+    let indices = &self.indices[..self.lods[0].count as usize];
+    self.bvh.get_or_init(|| Bvh::new(&self.vertices, indices)).raycast(ray, &self.vertices, indices, max_t)
+  }
+}
+
+impl Drop for Mesh {
+  fn drop(&mut self) {
+    pool::with(self.format(), |it| it.free(&self.slot))
+  }
+}
+
+pub enum Normals {
+  // whatever the file has, assimp fills in flat ones where it has none
+  Imported,
+  Flat,
+  // hard edges wherever faces meet at more than this many degrees
+  Smooth(f32)
 }
 
-pub struct Model(pub Vec<Mesh>);
+pub struct ImportOptions {
+  pub normals: Normals,
+  pub outline_normals: bool,
+  // maps material names straight to palette ramps, anything not in it
+  // gets the ramp nearest to its vertex colors or diffuse color
+  pub tints: HashMap<String, i32>,
+  pub lods: bool,
+  // see Model::packed
+  pub packed: bool,
+  // (name, path) of the same file exported with a blend shape applied, the meshes have to
+  // match the source's one for one and vertex for vertex
+  pub morph_targets: Vec<(String, String)>
+}
+
+impl ImportOptions {
+  fn post_process(&self) -> Vec<PostProcess> {
+    // only fills in meshes without normals, the rest is redone in cvt_mesh
+    let normals = match self.normals {
+      Normals::Smooth(_) => PostProcess::GenerateSmoothNormals,
+      _ => PostProcess::GenerateNormals
+    };
+
+    vec![PostProcess::Triangulate, normals, PostProcess::LimitBoneWeights]
+  }
+}
+
+impl Default for ImportOptions {
+  fn default() -> Self {
+    ImportOptions {
+      normals: Normals::Imported,
+      outline_normals: false,
+      tints: HashMap::new(),
+      lods: true,
+      packed: false,
+      morph_targets: Vec::new()
+    }
+  }
+}
+
+pub struct Material {
+  pub name: String,
+  pub tex_path: Option<String>,
+  // palette indexed, see TexSpec::palette_indexed
+  pub tex: Option<Tex>
+}
+
+pub struct Node {
+  pub name: String,
+  pub transform: Mat4,
+  pub parent: Option<usize>,
+  pub meshes: Vec<usize>
+}
+
+pub struct Model {
+  pub meshes: Vec<Mesh>,
+  pub materials: Vec<Material>,
+  // parents always come before their children
+  pub nodes: Vec<Node>,
+  pub bones: Vec<Bone>,
+  pub clips: Vec<Clip>,
+  // morph names across every mesh, meshes with a morph of the same name share its weight
+  pub morphs: Vec<String>,
+  // bind pose, in model space
+  pub bounds: Aabb
+}
+
+// keep in sync with skinned.vert
+pub const MAX_BONES: usize = 128;
 
 impl Model {
-  pub fn new(path: &str) -> Result<Model, String> {
+  // imports through a .hmesh cache next to the source that gets rebuilt whenever the source file,
+  // the files it pulls in, the palette or the options change. a stale or missing cache is just
+  // imported again, failing to write the new one is an error
+  pub fn load_with(path: &str, palette: &Palette, options: &ImportOptions) -> Result<Model, String> {
+    let stamp = cache::stamp(path, palette, options)?;
+    if let Ok(model) = cache::read(&cache::path(path), stamp, palette) {
+      return Ok(if options.packed { model.packed() } else { model });
+    }
+
+    let model = Self::import(path, palette, options)?;
+    cache::write(&model, path, stamp).map_err(|e| format!("failed to cache {}: {}", path, e))?;
+
+    Ok(if options.packed { model.packed() } else { model })
+  }
+
+  // everything but packing, the cache stores full precision vertices
+  fn import(path: &str, palette: &Palette, options: &ImportOptions) -> Result<Model, String> {
     let scene =
       Scene::
-      from_file(path, vec![PostProcess::Triangulate, PostProcess::GenerateNormals])
+      from_file(path, options.post_process())
         .map_err(|e| e.to_string())?;
     if let None = scene.root {
       return Err("Failed to load model!".into())
@@ -61,41 +330,509 @@ impl Model {
 
     let root = scene.root.as_deref().unwrap();
 
-    let res = Model(cvt_node(&root, &scene));
-    Ok(res)
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let (tints, materials): (Vec<_>, Vec<_>) =
+      scene.materials
+        .iter()
+        .map(|it| cvt_material(it, dir, palette, &options.tints))
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .unzip();
+    let mut nodes = Vec::new();
+    cvt_node(root, None, &mut nodes);
+
+    let targets = options.morph_targets.iter().map(|(name, path)| {
+      Ok((name.as_str(), Scene::from_file(path, options.post_process()).map_err(|e| path.clone() + ": " + &e.to_string())?))
+    }).collect::<Result<Vec<_>, String>>()?;
+
+    let mut bones = Vec::new();
+    let meshes = scene.meshes.iter().enumerate().map(|(i, it)| {
+      let targets = targets.iter().filter_map(|(name, scene)| Some((*name, scene.meshes.get(i)?))).collect::<Vec<_>>();
+      cvt_mesh(i, it, &vertex_tints(it, palette, &tints), &nodes, &mut bones, options, &targets)
+    }).collect::<Result<Vec<_>, String>>()?;
+    if bones.len() > MAX_BONES {
+      return Err(format!("{} has {} bones, max is {}", path, bones.len(), MAX_BONES));
+    }
+
+    let clips = scene.animations.iter().map(|it| cvt_clip(it, &nodes)).collect();
+
+    Ok(Self::assemble(meshes, materials, nodes, bones, clips))
+  }
+
+  pub fn assemble(meshes: Vec<Mesh>, materials: Vec<Material>, nodes: Vec<Node>, bones: Vec<Bone>, clips: Vec<Clip>) -> Model {
+    let mut res = Model { meshes, materials, nodes, bones, clips, morphs: Vec::new(), bounds: Aabb::EMPTY };
+    for mesh in &mut res.meshes {
+      for it in &mut mesh.morphs {
+        it.slot = match res.morphs.iter().position(|name| *name == it.name) {
+          Some(slot) => slot,
+          None => {
+            res.morphs.push(it.name.clone());
+            res.morphs.len() - 1
+          }
+        };
+      }
+    }
+
+    for (node, global) in zip(&res.nodes, res.globals()) {
+      for mesh in &node.meshes {
+        let mesh = &res.meshes[*mesh];
+        let bounds = if mesh.skinned { mesh.bounds } else { mesh.bounds.transform(&global) };
+        res.bounds = res.bounds.union(&bounds);
+      }
+    }
+
+    res
+  }
+
+  // one root node holding every mesh, for generated geometry
+  pub fn from_meshes(meshes: Vec<Mesh>) -> Model {
+    let root = Node { name: "root".into(), transform: Mat4::IDENTITY, parent: None, meshes: (0..meshes.len()).collect() };
+    Self::assemble(meshes, Vec::new(), vec![root], Vec::new(), Vec::new())
+  }
+
+  // quantised vertices on the gpu, see opt::pack
+  pub fn packed(mut self) -> Model {
+    for it in &mut self.meshes {
+      it.pack();
+    }
+
+    self
+  }
+
+  pub fn clip(&self, name: &str) -> Option<usize> {
+    self.clips.iter().position(|it| it.name == name)
+  }
+
+  pub fn morph(&self, name: &str) -> Option<usize> {
+    self.morphs.iter().position(|it| it == name)
+  }
+
+  pub fn bind_pose(&self) -> Vec<Transform> {
+    self.nodes.iter().map(|it| Transform::from_mat4(&it.transform)).collect()
+  }
+
+  pub fn globals(&self) -> Vec<Mat4> {
+    self.accumulate(|i| self.nodes[i].transform)
+  }
+
+  pub fn globals_posed(&self, pose: &[Transform]) -> Vec<Mat4> {
+    self.accumulate(|i| pose[i].matrix())
+  }
+
+  fn accumulate(&self, local: impl Fn(usize) -> Mat4) -> Vec<Mat4> {
+    let mut res: Vec<Mat4> = Vec::with_capacity(self.nodes.len());
+    for (i, node) in self.nodes.iter().enumerate() {
+      let global = match node.parent {
+        Some(parent) => res[parent] * local(i),
+        None => local(i)
+      };
+
+      res.push(global);
+    }
+
+    res
+  }
+
+  // the closest mesh hit along a model space ray, see globals / globals_posed. hits come back in model space
+  pub fn raycast(&self, ray: &Ray, globals: &[Mat4], max_t: f32) -> Option<(usize, Hit)> {
+    let mut best: Option<(usize, Hit)> = None;
+    for (node, global) in zip(&self.nodes, globals) {
+      for i in &node.meshes {
+        let mesh = &self.meshes[*i];
+        let mat = if mesh.skinned { Mat4::IDENTITY } else { *global };
+        let max_t = best.map_or(max_t, |it| it.1.t);
+        if ray.intersects_aabb(&mesh.bounds.transform(&mat), max_t).is_none() {
+          continue;
+        }
+
+        if let Some(hit) = mesh.raycast(&ray.transform(&mat.inverse()), max_t) {
+          let norm = (norm_mat(&mat) * hit.norm).normalize_or_zero();
+          best = Some((*i, Hit { pos: mat.transform_point3(hit.pos), norm, ..hit }));
+        }
+      }
+    }
+
+    best
+  }
+
+  pub fn draw(&self, shader: &Shader, model: &Mat4) {
+    self.draw_globals(shader, model, &self.globals(), &[], None)
+  }
+
+  pub fn draw_culled(&self, shader: &Shader, model: &Mat4, view: &View, stats: &mut CullStats) {
+    self.draw_globals(shader, model, &self.globals(), &[], Some((view, stats)))
+  }
+
+  // skinned models need skinned.vert
+  // `morphs` are weights per Model::morphs, missing ones count as 0
+  pub fn draw_posed(&self, shader: &Shader, model: &Mat4, pose: &[Transform], morphs: &[f32], cull: Option<(&View, &mut CullStats)>) {
+    self.draw_globals(shader, model, &self.globals_posed(pose), morphs, cull)
+  }
+
+  // skinned meshes are never culled on their own, their bind pose bounds say little about the current pose.
+  // without a view everything is drawn at full detail
+  fn draw_globals(&self, shader: &Shader, model: &Mat4, globals: &[Mat4], morphs: &[f32], mut cull: Option<(&View, &mut CullStats)>) {
+    if !self.bones.is_empty() {
+      let bones = self.bones.iter().map(|it| globals[it.node] * it.offset).collect::<Vec<_>>();
+      shader.set_mat4v("u_bones", &bones);
+    }
+
+    for (node, global) in zip(&self.nodes, globals) {
+      for mesh in &node.meshes {
+        let mesh = &self.meshes[*mesh];
+        let mat = if mesh.skinned { *model } else { *model * *global };
+        let mut lod = mesh.lods[0];
+        if let Some((view, stats)) = &mut cull {
+          let bounds = mesh.bounds.transform(&mat);
+          if !mesh.skinned && !view.frustum.intersects_aabb(&bounds) {
+            stats.meshes_culled += 1;
+            continue;
+          }
+
+          lod = lod::select(&mesh.lods, lod::level(view.coverage(&bounds.sphere())));
+          stats.meshes_drawn += 1;
+          stats.triangles_drawn += lod.count / 3;
+        }
+
+        shader.set_mat4("u_model", &mat);
+        shader.set_mat3("u_norm", &norm_mat(&mat));
+        if !self.bones.is_empty() {
+          shader.set_1i("u_skinned", mesh.skinned as i32);
+        }
+
+        // every mesh, the last one's morphs would otherwise stay bound
+        self.bind_mesh(shader, mesh);
+        self.bind_morphs(shader, mesh, morphs);
+
+        pool::with(mesh.format(), |it| it.vao.bind());
+        gl_draw_elements_base_vertex(gl::TRIANGLES, lod.count as i32, (mesh.slot.first_index + lod.start) as usize, mesh.slot.base_vertex);
+        if let Some((_, stats)) = &mut cull {
+          stats.draw_calls += 1;
+        }
+      }
+    }
+  }
+
+  // an inverted hull, each mesh pushed `width` out along its outline normals with its front faces
+  // culled so only the rim around the model shows. needs model.vert, so skinned meshes and
+  // meshes imported without ImportOptions::outline_normals are skipped
+  pub fn draw_outline(&self, shader: &Shader, model: &Mat4, width: f32) {
+    gl_enable(gl::CULL_FACE);
+    gl_cull_face(gl::FRONT);
+    shader.set_1f("u_outline", width);
+    shader.set_1i("u_morph_count", 0);
+    for (node, global) in zip(&self.nodes, self.globals()) {
+      for mesh in &node.meshes {
+        let mesh = &self.meshes[*mesh];
+        let Some(buf) = mesh.outline_buf.as_ref().filter(|_| !mesh.skinned) else {
+          continue;
+        };
+
+        let mat = *model * global;
+        buf.bind_base(OUTLINE_BINDING);
+        shader.set_1i("u_base_vertex", mesh.slot.base_vertex);
+        shader.set_mat4("u_model", &mat);
+        shader.set_mat3("u_norm", &norm_mat(&mat));
+        self.bind_mesh(shader, mesh);
+        pool::with(mesh.format(), |it| it.vao.bind());
+        gl_draw_elements_base_vertex(gl::TRIANGLES, mesh.lods[0].count as i32, mesh.slot.first_index as usize, mesh.slot.base_vertex);
+      }
+    }
+
+    shader.set_1f("u_outline", 0.);
+    gl_disable(gl::CULL_FACE);
+  }
+
+  // a command per mesh covering every instance at `level`, see batch::Batches
+  pub fn commands(&self, level: usize, instances: &[Instance], draws: &mut Vec<DrawData>, groups: &mut DrawGroups, stats: &mut CullStats) {
+    for (node, global) in zip(&self.nodes, self.globals()) {
+      for mesh in &node.meshes {
+        let mesh = &self.meshes[*mesh];
+        let lod = lod::select(&mesh.lods, level);
+        let global = if mesh.skinned { Mat4::IDENTITY } else { global };
+        let tex = self.materials.get(mesh.material).and_then(|it| it.tex.as_ref()).map(|it| it.id);
+        groups.entry((mesh.format(), tex)).or_default().push(Command {
+          count: lod.count,
+          instance_count: instances.len() as u32,
+          first_index: mesh.slot.first_index + lod.start,
+          base_vertex: mesh.slot.base_vertex,
+          base_instance: draws.len() as u32
+        });
+
+        draws.extend(instances.iter().map(|it| DrawData::new(&(Mat4::from_cols_array(&it.model) * global), mesh, it.tint, it.id)));
+        stats.meshes_drawn += instances.len() as u32;
+        stats.triangles_drawn += lod.count / 3 * instances.len() as u32;
+      }
+    }
+  }
+
+  fn bind_morphs(&self, shader: &Shader, mesh: &Mesh, morphs: &[f32]) {
+    let weights = mesh.morphs.iter().take(MAX_MORPHS).map(|it| morphs.get(it.slot).copied().unwrap_or(0.)).collect::<Vec<_>>();
+    let Some(buf) = mesh.morph_buf.as_ref().filter(|_| weights.iter().any(|it| *it != 0.)) else {
+      shader.set_1i("u_morph_count", 0);
+      return;
+    };
+
+    buf.bind_base(MORPH_BINDING);
+    shader.set_1i("u_morph_count", weights.len() as i32);
+    shader.set_1fv("u_morph_weights", &weights);
+    shader.set_1i("u_morph_vertices", mesh.vertices.len() as i32);
+    shader.set_1i("u_base_vertex", mesh.slot.base_vertex);
+  }
+
+  fn bind_mesh(&self, shader: &Shader, mesh: &Mesh) {
+    shader.set_1i("u_packed", mesh.packed as i32);
+    if mesh.packed {
+      shader.set_3f("u_quant_min", &mesh.bounds.min);
+      shader.set_3f("u_quant_size", &(mesh.bounds.max - mesh.bounds.min));
+    }
+
+    match self.materials.get(mesh.material).and_then(|it| it.tex.as_ref()) {
+      Some(tex) => {
+        tex.bind(gl::TEXTURE3);
+        shader.set_1i("u_index_tex", 3);
+        shader.set_1i("u_indexed", 1);
+      }
+      None => shader.set_1i("u_indexed", 0)
+    }
   }
 }
 
-fn cvt_node(root: &russimp::node::Node, scene: &Scene) -> Vec<Mesh> {
-  let mut res = Vec::new();
-  for mesh in &root.meshes {
-    res.push(cvt_mesh(&scene.meshes[*mesh as usize]));
+// mirrors x like cvt_mesh does, so node transforms act on the flipped vertices
+const FLIP_X: Mat4 = Mat4::from_cols_array(&[
+  -1., 0., 0., 0.,
+  0., 1., 0., 0.,
+  0., 0., 1., 0.,
+  0., 0., 0., 1.,
+]);
+
+fn cvt_node(node: &russimp::node::Node, parent: Option<usize>, nodes: &mut Vec<Node>) {
+  let idx = nodes.len();
+  nodes.push(Node {
+    name: node.name.clone(),
+    transform: FLIP_X * node.transformation.cvt() * FLIP_X,
+    parent,
+    meshes: node.meshes.iter().map(|it| *it as usize).collect()
+  });
+
+  for child in node.children.borrow().deref() {
+    cvt_node(child, Some(idx), nodes)
   }
+}
+
+// explicit table entries win over everything, otherwise the diffuse color is only a fallback
+enum MaterialTint {
+  Fixed(i32),
+  Diffuse(i32),
+  None
+}
 
-  for child in root.children.borrow().deref() {
-    res.append(&mut cvt_node(child, scene))
+fn cvt_material(mat: &AiMaterial, dir: &Path, palette: &Palette, table: &HashMap<String, i32>) -> Result<(MaterialTint, Material), String> {
+  let mut name = None;
+  let mut diffuse = None;
+  let mut tex_file = None;
+  for prop in &mat.properties {
+    match (prop.key.as_str(), &prop.data) {
+      ("?mat.name", PropertyTypeInfo::String(it)) => name = Some(it),
+      ("$clr.diffuse", PropertyTypeInfo::FloatArray(it)) if it.len() >= 3 => diffuse = Some(Vec3::new(it[0], it[1], it[2])),
+      ("$tex.file", PropertyTypeInfo::String(it)) if prop.semantic == TextureType::Diffuse && prop.index == 0 => tex_file = Some(it),
+      _ => {}
+    }
   }
 
-  res
+  let tint = match (name.and_then(|it| table.get(it)), diffuse) {
+    (Some(tint), _) => MaterialTint::Fixed(*tint),
+    (None, Some(it)) => MaterialTint::Diffuse(palette.nearest_ramp(it)),
+    (None, None) => MaterialTint::None
+  };
+
+  let tex_path = tex_file.map(|it| dir.join(it).to_string_lossy().to_string());
+  let tex = match &tex_path {
+    Some(path) => Some(Tex::new(&TexSpec::palette_indexed(path, palette)?)),
+    None => None
+  };
+
+  Ok((tint, Material { name: name.cloned().unwrap_or_default(), tex_path, tex }))
+}
+
+// a palette ramp per vertex
+fn vertex_tints(mesh: &russimp::mesh::Mesh, palette: &Palette, tints: &[MaterialTint]) -> Vec<i32> {
+  let colors = mesh.colors.first().and_then(|it| it.as_ref());
+  let mat_tint = tints.get(mesh.material_index as usize).unwrap_or(&MaterialTint::None);
+  (0..mesh.vertices.len()).map(|i| match (mat_tint, colors) {
+    (MaterialTint::Fixed(tint), _) => *tint,
+    (_, Some(colors)) => palette.nearest_ramp(Vec3::new(colors[i].r, colors[i].g, colors[i].b)),
+    (MaterialTint::Diffuse(tint), None) => *tint,
+    (MaterialTint::None, None) => -1
+  }).collect()
 }
 
-fn cvt_mesh(mesh: &russimp::mesh::Mesh) -> Mesh {
+fn cvt_mesh(
+  idx: usize,
+  mesh: &russimp::mesh::Mesh,
+  tints: &[i32],
+  nodes: &[Node],
+  bones: &mut Vec<Bone>,
+  options: &ImportOptions,
+  targets: &[(&str, &russimp::mesh::Mesh)]
+) -> Result<Mesh, String> {
   let mut vertices = Vec::new();
   let mut indices = Vec::new();
 
-  for i in zip(&mesh.vertices, &mesh.normals) {
+  let uvs = mesh.texture_coords.first().and_then(|it| it.as_ref());
+  for (i, it) in zip(&mesh.vertices, &mesh.normals).enumerate() {
     vertices.push(Vertex {
-      pos: i.0.cvt() * Vec3::new(-1., 1., 1.),
-      norm: i.1.cvt() * Vec3::new(-1., 1., 1.),
-      tint: Vec4::ONE
+      pos: it.0.cvt() * Vec3::new(-1., 1., 1.),
+      norm: it.1.cvt() * Vec3::new(-1., 1., 1.),
+      tint: tints[i],
+      tex: uvs.map_or(Vec2::ZERO, |it| Vec2::new(it[i].x, it[i].y)),
+      bones: IVec4::ZERO,
+      weights: Vec4::ZERO
     })
   }
 
+  for bone in &mesh.bones {
+    let idx = match bones.iter().position(|it| it.name == bone.name) {
+      Some(idx) => idx,
+      None => {
+        let node = nodes.iter().position(|it| it.name == bone.name).ok_or_else(|| format!("bone {} on {} has no node", bone.name, mesh.name))?;
+        bones.push(Bone { name: bone.name.clone(), node, offset: FLIP_X * bone.offset_matrix.cvt() * FLIP_X });
+        bones.len() - 1
+      }
+    };
+
+    for weight in &bone.weights {
+      add_influence(&mut vertices[weight.vertex_id as usize], idx as i32, weight.weight);
+    }
+  }
+
+  // vertices no bone pulls on follow the mesh's own node, through a bone with no offset
+  let holder = nodes.iter().position(|it| it.meshes.contains(&idx));
+  let mut rigid = None;
+  for it in &mut vertices {
+    // copied out since the vertex is packed
+    let weights = it.weights;
+    let sum = weights.dot(Vec4::ONE);
+    if sum > 0. {
+      it.weights = weights / sum;
+    } else if let (false, Some(node)) = (mesh.bones.is_empty(), holder) {
+      let bone = *rigid.get_or_insert_with(|| match bones.iter().position(|it| it.node == node && it.offset == Mat4::IDENTITY) {
+        Some(bone) => bone,
+        None => {
+          bones.push(Bone { name: nodes[node].name.clone(), node, offset: Mat4::IDENTITY });
+          bones.len() - 1
+        }
+      });
+
+      it.bones = IVec4::new(bone as i32, 0, 0, 0);
+      it.weights = Vec4::X;
+    }
+  }
+
   for face in &mesh.faces {
     for index in &face.0 {
       indices.push(*index)
     }
   }
 
-  Mesh::new(vertices, indices)
+  // russimp's AnimMesh holds the target's bitangents rather than its positions (see the test below),
+  // so blend shapes inside the file are unusable. they come from ImportOptions::morph_targets instead
+  if !mesh.anim_meshes.is_empty() && targets.is_empty() {
+    return Err(format!("{} has {} blend shapes that can't be read, pass them as ImportOptions::morph_targets", mesh.name, mesh.anim_meshes.len()));
+  }
+
+  let flip = Vec3::new(-1., 1., 1.);
+  let morphs = targets.iter().map(|(name, target)| {
+    if target.vertices.len() != vertices.len() || target.normals.len() != vertices.len() {
+      return Err(format!("morph {} doesn't match {}, {} vertices instead of {}", name, mesh.name, target.vertices.len(), vertices.len()));
+    }
+
+    let pos = zip(&target.vertices, &vertices).map(|(it, base)| it.cvt() * flip - base.pos).collect::<Vec<_>>();
+    let norm = zip(&target.normals, &vertices).map(|(it, base)| it.cvt() * flip - base.norm).collect::<Vec<_>>();
+    Ok(Morph { name: name.to_string(), pos, norm, slot: 0 })
+  }).collect::<Result<Vec<_>, String>>()?;
+
+  // generated normals give every corner its own vertex, the deltas have to follow
+  let morphs = match options.normals {
+    Normals::Imported => morphs,
+    _ => morphs.into_iter().map(|it| Morph {
+      pos: indices.iter().map(|i| it.pos[*i as usize]).collect(),
+      norm: indices.iter().map(|i| it.norm[*i as usize]).collect(),
+      ..it
+    }).collect()
+  };
+
+  let (vertices, indices) = match options.normals {
+    Normals::Imported => (vertices, indices),
+    Normals::Flat => normals::flat(&vertices, &indices),
+    Normals::Smooth(angle) => normals::smooth(&vertices, &indices, angle)
+  };
+
+  let mut res = Mesh::optimized_morphs(vertices, indices, options.lods, morphs);
+  if options.outline_normals {
+    res.set_outline(normals::outline(&res.vertices, &res.indices[..res.lods[0].count as usize]));
+  }
+
+  res.material = mesh.material_index as usize;
+  res.skinned = !mesh.bones.is_empty();
+  Ok(res)
+}
+
+// replaces the weakest slot, LimitBoneWeights means this rarely has to drop anything
+fn add_influence(vertex: &mut Vertex, bone: i32, weight: f32) {
+  let mut bones = { vertex.bones }.to_array();
+  let mut weights = { vertex.weights }.to_array();
+  let mut min = 0;
+  for i in 1..4 {
+    if weights[i] < weights[min] {
+      min = i;
+    }
+  }
+
+  if weights[min] < weight {
+    bones[min] = bone;
+    weights[min] = weight;
+  }
+
+  vertex.bones = IVec4::from_array(bones);
+  vertex.weights = Vec4::from_array(weights);
+}
+
+fn cvt_clip(anim: &russimp::animation::Animation, nodes: &[Node]) -> Clip {
+  // assimp leaves ticks_per_second at 0 when the file doesn't say
+  let tps = if anim.ticks_per_second > 0. { anim.ticks_per_second } else { 25. };
+  let time = |t: f64| (t / tps) as f32;
+
+  let channels = anim.channels.iter().filter_map(|channel| {
+    let node = nodes.iter().position(|it| it.name == channel.name)?;
+    Some(Channel {
+      node,
+      pos: channel.position_keys.iter().map(|it| (time(it.time), it.value.cvt() * Vec3::new(-1., 1., 1.))).collect(),
+      rot: channel.rotation_keys.iter().map(|it| (time(it.time), it.value.cvt())).collect(),
+      scale: channel.scaling_keys.iter().map(|it| (time(it.time), it.value.cvt())).collect()
+    })
+  }).collect();
+
+  Clip { name: anim.name.clone(), duration: time(anim.duration), channels }
+}
+
+#[cfg(test)]
+mod tests {
+  use russimp::mesh::AnimMesh;
+  use russimp::sys::{aiAnimMesh, aiVector3D};
+
+  // why cvt_mesh won't take blend shapes out of the file itself
+  #[test]
+  fn russimp_anim_meshes_hold_bitangents() {
+    let mut vertices = [aiVector3D { x: 1., y: 2., z: 3. }];
+    let mut bitangents = [aiVector3D { x: 4., y: 5., z: 6. }];
+    // every field is a pointer, a count or plain data, so zeroed is a valid empty anim mesh
+    let mut raw: aiAnimMesh = unsafe { std::mem::zeroed() };
+    raw.mVertices = vertices.as_mut_ptr();
+    raw.mBitangents = bitangents.as_mut_ptr();
+    raw.mNumVertices = 1;
+
+    let mesh = AnimMesh::from(&raw);
+    assert_eq!(mesh.0.iter().map(|it| [it.x, it.y, it.z]).collect::<Vec<_>>(), [[4., 5., 6.]]);
+  }
 }
\ No newline at end of file
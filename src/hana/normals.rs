@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use glam::Vec3;
+use crate::hana::model::Vertex;
+
+// vertices sharing a position, however their other attributes differ
+fn weld(vertices: &[Vertex]) -> (Vec<usize>, usize) {
+  let mut by_pos = HashMap::new();
+  let group = vertices.iter().map(|it| {
+    let key = { it.pos }.to_array().map(f32::to_bits);
+    let n = by_pos.len();
+    *by_pos.entry(key).or_insert(n)
+  }).collect();
+
+  (group, by_pos.len())
+}
+
+// unnormalised, so sums come out area weighted. the import mirrors x without rewinding, so
+// these are turned to agree with the normals already on the corners rather than trusting the winding
+fn face_norms(vertices: &[Vertex], indices: &[u32]) -> Vec<Vec3> {
+  indices.chunks_exact(3).map(|it| {
+    let [a, b, c] = [0, 1, 2].map(|i| &vertices[it[i] as usize]);
+    let norm = (b.pos - a.pos).cross(c.pos - a.pos);
+    if norm.dot(a.norm + b.norm + c.norm) < 0. { -norm } else { norm }
+  }).collect()
+}
+
+// every triangle gets its own corners, `norm` picks each corner's normal from
+// (triangle, corner index). the optimiser welds whatever comes out identical
+fn unweld(vertices: &[Vertex], indices: &[u32], norm: impl Fn(usize, usize) -> Vec3) -> (Vec<Vertex>, Vec<u32>) {
+  let mut res = Vec::with_capacity(indices.len());
+  for (t, tri) in indices.chunks_exact(3).enumerate() {
+    for it in tri {
+      let mut vertex = vertices[*it as usize].clone();
+      let norm = norm(t, *it as usize);
+      if norm != Vec3::ZERO {
+        vertex.norm = norm;
+      }
+
+      res.push(vertex);
+    }
+  }
+
+  (res, (0..indices.len() as u32).collect())
+}
+
+// faceted, every corner takes its triangle's normal
+pub fn flat(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+  let faces = face_norms(vertices, indices);
+  unweld(vertices, indices, |t, _| faces[t].normalize_or_zero())
+}
+
+// averages the triangles around each position whose normals are within `angle` degrees
+// of the corner's own triangle, anything sharper stays a hard edge
+pub fn smooth(vertices: &[Vertex], indices: &[u32], angle: f32) -> (Vec<Vertex>, Vec<u32>) {
+  let faces = face_norms(vertices, indices);
+  let units = faces.iter().map(|it| it.normalize_or_zero()).collect::<Vec<_>>();
+  let (group, n_groups) = weld(vertices);
+  let mut around = vec![Vec::new(); n_groups];
+  for (t, tri) in indices.chunks_exact(3).enumerate() {
+    for it in tri {
+      around[group[*it as usize]].push(t);
+    }
+  }
+
+  let min_cos = angle.to_radians().cos();
+  unweld(vertices, indices, |t, i| {
+    around[group[i]].iter()
+      .filter(|it| units[**it].dot(units[t]) >= min_cos)
+      .map(|it| faces[*it])
+      .sum::<Vec3>()
+      .normalize_or_zero()
+  })
+}
+
+// one normal per position averaged over every triangle touching it, so pushing vertices out
+// along these for an inverted hull never tears open at hard edges or uv seams
+pub fn outline(vertices: &[Vertex], indices: &[u32]) -> Vec<Vec3> {
+  let faces = face_norms(vertices, indices);
+  let (group, n_groups) = weld(vertices);
+  let mut sums = vec![Vec3::ZERO; n_groups];
+  for (t, tri) in indices.chunks_exact(3).enumerate() {
+    for it in tri {
+      sums[group[*it as usize]] += faces[t];
+    }
+  }
+
+  group.iter().enumerate().map(|(i, it)| {
+    let norm = sums[*it].normalize_or_zero();
+    if norm == Vec3::ZERO { vertices[i].norm } else { norm }
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vertex(pos: Vec3) -> Vertex {
+    Vertex { pos, norm: pos.normalize(), ..Vertex::empty() }
+  }
+
+  // corners of a 2 unit cube shared between faces, normals pointing out through the corners
+  fn cube() -> (Vec<Vertex>, Vec<u32>) {
+    let vertices = (0..8).map(|i| vertex(Vec3::new([-1., 1.][i & 1], [-1., 1.][i >> 1 & 1], [-1., 1.][i >> 2 & 1]))).collect();
+    let faces = [[0, 2, 6, 4], [1, 5, 7, 3], [0, 4, 5, 1], [2, 3, 7, 6], [0, 1, 3, 2], [4, 6, 7, 5]];
+    let indices = faces.iter().flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d]).collect();
+    (vertices, indices)
+  }
+
+  fn octahedron() -> (Vec<Vertex>, Vec<u32>) {
+    let vertices = [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z].map(vertex).to_vec();
+    let indices = [0, 2, 4, 2, 1, 4, 1, 3, 4, 3, 0, 4, 2, 0, 5, 1, 2, 5, 3, 1, 5, 0, 3, 5].to_vec();
+    (vertices, indices)
+  }
+
+  fn near(a: Vec3, b: Vec3) -> bool {
+    a.distance(b) < 1e-5
+  }
+
+  #[test]
+  fn flat_gives_face_normals() {
+    let (vertices, indices) = cube();
+    let (vertices, indices) = flat(&vertices, &indices);
+    assert_eq!(vertices.len(), 36);
+    for tri in indices.chunks_exact(3) {
+      let [a, b, c] = [0, 1, 2].map(|i| &vertices[tri[i] as usize]);
+      // the face's axis is the one all three corners agree on
+      let center = (a.pos + b.pos + c.pos) / 3.;
+      let axis = Vec3::select(center.abs().cmpeq(Vec3::ONE), center, Vec3::ZERO);
+      assert!([a, b, c].iter().all(|it| near(it.norm, axis)), "{} for a face on {}", { a.norm }, axis);
+    }
+  }
+
+  #[test]
+  fn smooth_averages_within_the_angle() {
+    let (vertices, indices) = cube();
+    // 90 degree edges stay hard under 90
+    let (hard, _) = smooth(&vertices, &indices, 60.);
+    let (faceted, _) = flat(&vertices, &indices);
+    assert!(hard.iter().zip(&faceted).all(|(a, b)| near(a.norm, b.norm)));
+
+    // and every corner is shared above it, pointing out through the corner
+    let (soft, _) = smooth(&vertices, &indices, 100.);
+    for it in &soft {
+      let norm = it.norm;
+      assert!(near(norm, soft.iter().find(|other| other.pos == it.pos).unwrap().norm));
+      assert!((norm * it.pos).cmpgt(Vec3::ZERO).all(), "{} at {}", norm, { it.pos });
+    }
+
+    // faces around an octahedron's tip are 70.5 degrees from their neighbours and 109.5 from
+    // the one opposite, so 80 takes three of the four and 120 all of them
+    let (vertices, indices) = octahedron();
+    let (some, _) = smooth(&vertices, &indices, 80.);
+    assert!(near(some[0].norm, Vec3::new(3., 1., 1.).normalize()));
+    let (all, _) = smooth(&vertices, &indices, 120.);
+    assert!(near(all[0].norm, Vec3::X));
+  }
+
+  #[test]
+  fn outline_normals_are_welded_and_push_out() {
+    let (vertices, indices) = cube();
+    let (vertices, indices) = flat(&vertices, &indices);
+    let outline = outline(&vertices, &indices);
+    assert_eq!(outline.len(), vertices.len());
+    for (it, norm) in vertices.iter().zip(&outline) {
+      // the same for every corner at a position, so the hull doesn't tear at the hard edges
+      let first = vertices.iter().position(|other| other.pos == it.pos).unwrap();
+      assert!(near(*norm, outline[first]));
+      assert!((*norm * it.pos).cmpgt(Vec3::ZERO).all(), "{} at {}", norm, { it.pos });
+      assert!((it.pos + *norm * 0.1).length() > it.pos.length());
+    }
+  }
+}
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use glam::{Vec2, Vec2Swizzles, Vec3, Vec3Swizzles};
+use crate::hana::bounds::Aabb;
+use crate::hana::glu::{Attrib, HALF_2, INT_1, SHORT_2_NORM, UBYTE_4, UBYTE_4_NORM, USHORT_4_NORM};
+use crate::hana::model::Vertex;
+
+// simulated post-transform cache, modern hardware behaves roughly like this
+const CACHE_SIZE: usize = 32;
+
+// keep in sync with model.vert / skinned.vert when u_packed is set
+pub const PACKED_ATTRIBS: [Attrib; 6] = [USHORT_4_NORM, SHORT_2_NORM, INT_1, HALF_2, UBYTE_4, UBYTE_4_NORM];
+
+// 28 bytes instead of Vertex's 68
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PackedVertex {
+  // 0..1 across the mesh bounds, w is padding
+  pub pos: [u16; 4],
+  // octahedral
+  pub norm: [i16; 2],
+  pub tint: i32,
+  pub tex: [u16; 2],
+  pub bones: [u8; 4],
+  pub weights: [u8; 4]
+}
+
+fn vertex_bytes(vertex: &Vertex) -> &[u8] {
+  unsafe { std::slice::from_raw_parts(vertex as *const Vertex as *const u8, size_of::<Vertex>()) }
+}
+
+// merges bitwise identical vertices, importers without JoinIdenticalVertices emit plenty.
+// `extra` is anything else per vertex that has to match too, e.g. morph deltas, and can be empty.
+// also returns which source vertex each one came from
+pub fn dedup(vertices: &[Vertex], indices: &[u32], extra: &[Vec<u8>]) -> (Vec<Vertex>, Vec<u32>, Vec<u32>) {
+  let mut seen = HashMap::new();
+  let mut res = Vec::new();
+  let mut origin = Vec::new();
+  let remap = vertices.iter().enumerate().map(|(i, it)| {
+    let key = (vertex_bytes(it), extra.get(i).map_or(&[][..], |it| &it[..]));
+    *seen.entry(key).or_insert_with(|| {
+      res.push(it.clone());
+      origin.push(i as u32);
+      res.len() as u32 - 1
+    })
+  }).collect::<Vec<_>>();
+
+  (res, indices.iter().map(|it| remap[*it as usize]).collect(), origin)
+}
+
+// forsyth's "linear-speed vertex cache optimisation"
+fn vertex_score(cache_pos: Option<usize>, remaining: usize) -> f32 {
+  if remaining == 0 {
+    return -1.;
+  }
+
+  let cached = match cache_pos {
+    None => 0.,
+    // the last triangle's vertices, deliberately lower so strips don't just ping-pong
+    Some(pos) if pos < 3 => 0.75,
+    Some(pos) => (1. - (pos - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5)
+  };
+
+  // favour finishing off vertices with few triangles left
+  cached + 2. * (remaining as f32).powf(-0.5)
+}
+
+pub fn optimize_cache(indices: &[u32], n_vertices: usize) -> Vec<u32> {
+  let tris = indices.chunks_exact(3).map(|it| [it[0], it[1], it[2]]).collect::<Vec<_>>();
+  let mut tris_of = vec![Vec::new(); n_vertices];
+  for (t, tri) in tris.iter().enumerate() {
+    for it in tri {
+      tris_of[*it as usize].push(t);
+    }
+  }
+
+  let mut cache_pos = vec![None; n_vertices];
+  let mut score = tris_of.iter().map(|it| vertex_score(None, it.len())).collect::<Vec<_>>();
+  let tri_score = |score: &[f32], tri: &[u32; 3]| tri.iter().map(|it| score[*it as usize]).sum::<f32>();
+  let mut scores = tris.iter().map(|it| tri_score(&score, it)).collect::<Vec<_>>();
+  let mut emitted = vec![false; tris.len()];
+  let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+  let mut res = Vec::with_capacity(indices.len());
+
+  let best_remaining = |scores: &[f32], emitted: &[bool]| {
+    (0..tris.len()).filter(|it| !emitted[*it]).max_by(|a, b| scores[*a].total_cmp(&scores[*b]))
+  };
+
+  let mut best = best_remaining(&scores, &emitted);
+  while let Some(t) = best {
+    let tri = tris[t];
+    emitted[t] = true;
+    res.extend_from_slice(&tri);
+    for it in tri {
+      tris_of[it as usize].retain(|it| *it != t);
+    }
+
+    let mut next = tri.to_vec();
+    next.extend(cache.iter().filter(|it| !tri.contains(it)));
+    let evicted = next.split_off(next.len().min(CACHE_SIZE));
+    for it in &evicted {
+      cache_pos[*it as usize] = None;
+    }
+
+    cache = next;
+    for (i, it) in cache.iter().enumerate() {
+      cache_pos[*it as usize] = Some(i);
+    }
+
+    for it in cache.iter().chain(&evicted) {
+      score[*it as usize] = vertex_score(cache_pos[*it as usize], tris_of[*it as usize].len());
+    }
+
+    // only triangles touching the cache can have changed
+    best = None;
+    let mut best_score = f32::MIN;
+    for it in cache.iter().chain(&evicted) {
+      for t in &tris_of[*it as usize] {
+        scores[*t] = tri_score(&score, &tris[*t]);
+        if scores[*t] > best_score {
+          best = Some(*t);
+          best_score = scores[*t];
+        }
+      }
+    }
+
+    // ran dry, start on the next disconnected piece
+    if best.is_none() {
+      best = best_remaining(&scores, &emitted);
+    }
+  }
+
+  res
+}
+
+// sander et al, "fast triangle reordering for vertex locality and reduced overdraw". the cache
+// ordered triangles are cut into clusters wherever the cache would have started over, then
+// clusters facing away from the middle of the mesh go first since they tend to occlude the rest
+pub fn optimize_overdraw(vertices: &[Vertex], indices: &[u32]) -> Vec<u32> {
+  let mut clusters = Vec::new();
+  let mut fifo: Vec<u32> = Vec::with_capacity(CACHE_SIZE);
+  let mut start = 0;
+  for (t, tri) in indices.chunks_exact(3).enumerate() {
+    let mut misses = 0;
+    for it in tri {
+      if !fifo.contains(it) {
+        misses += 1;
+        if fifo.len() == CACHE_SIZE {
+          fifo.remove(0);
+        }
+
+        fifo.push(*it);
+      }
+    }
+
+    if misses == 3 && t * 3 > start {
+      clusters.push(start..t * 3);
+      start = t * 3;
+    }
+  }
+
+  clusters.push(start..indices.len());
+
+  let pos = |it: &u32| vertices[*it as usize].pos;
+  let center = Aabb::from_points(indices.iter().map(pos)).center();
+  let mut keyed = clusters.into_iter().map(|range| {
+    let mut centroid = Vec3::ZERO;
+    let mut norm = Vec3::ZERO;
+    let mut area = 0.;
+    for tri in indices[range.clone()].chunks_exact(3) {
+      let [a, b, c] = [pos(&tri[0]), pos(&tri[1]), pos(&tri[2])];
+      let cross = (b - a).cross(c - a);
+      centroid += (a + b + c) / 3. * cross.length();
+      norm += cross;
+      area += cross.length();
+    }
+
+    let centroid = if area > 0. { centroid / area } else { center };
+    ((centroid - center).dot(norm.normalize_or_zero()), range)
+  }).collect::<Vec<_>>();
+
+  keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+  keyed.into_iter().flat_map(|(_, range)| indices[range].to_vec()).collect()
+}
+
+// puts vertices in the order the indices first touch them, vertices nothing uses are dropped.
+// also returns where each one used to be
+pub fn optimize_fetch(vertices: &[Vertex], indices: &mut [u32]) -> (Vec<Vertex>, Vec<u32>) {
+  let mut remap = vec![u32::MAX; vertices.len()];
+  let mut res = Vec::with_capacity(vertices.len());
+  let mut origin = Vec::with_capacity(vertices.len());
+  for it in indices {
+    if remap[*it as usize] == u32::MAX {
+      remap[*it as usize] = res.len() as u32;
+      res.push(vertices[*it as usize].clone());
+      origin.push(*it);
+    }
+
+    *it = remap[*it as usize];
+  }
+
+  (res, origin)
+}
+
+// round to nearest, overflow goes to infinity
+fn f16(val: f32) -> u16 {
+  let bits = val.to_bits();
+  let sign = ((bits >> 16) & 0x8000) as u16;
+  let exp = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+  let mant = bits & 0x7fffff;
+  if exp >= 31 {
+    let nan = (bits >> 23) & 0xff == 0xff && mant != 0;
+    return sign | 0x7c00 | if nan { 0x200 } else { 0 };
+  }
+
+  if exp <= 0 {
+    if exp < -10 {
+      return sign;
+    }
+
+    return sign | ((mant | 0x800000) >> (14 - exp)) as u16;
+  }
+
+  (sign | ((exp as u16) << 10) | (mant >> 13) as u16) + ((mant >> 12) & 1) as u16
+}
+
+fn oct_encode(norm: Vec3) -> [i16; 2] {
+  let norm = norm / (norm.x.abs() + norm.y.abs() + norm.z.abs()).max(1e-8);
+  let mut res = norm.xy();
+  if norm.z < 0. {
+    let sign = Vec2::new(if res.x >= 0. { 1. } else { -1. }, if res.y >= 0. { 1. } else { -1. });
+    res = (Vec2::ONE - res.yx().abs()) * sign;
+  }
+
+  res.to_array().map(|it| (it.clamp(-1., 1.) * 32767.).round() as i16)
+}
+
+fn unorm16(val: f32) -> u16 {
+  (val.clamp(0., 1.) * 65535.).round() as u16
+}
+
+fn unorm8(val: f32) -> u8 {
+  (val.clamp(0., 1.) * 255.).round() as u8
+}
+
+// positions are quantised across `bounds`, the shader gets them back with u_quant_min / u_quant_size
+pub fn pack(vertices: &[Vertex], bounds: &Aabb) -> Vec<PackedVertex> {
+  let size = (bounds.max - bounds.min).max(Vec3::splat(1e-8));
+  vertices.iter().map(|it| {
+    let pos = (it.pos - bounds.min) / size;
+    PackedVertex {
+      pos: [unorm16(pos.x), unorm16(pos.y), unorm16(pos.z), 0],
+      norm: oct_encode(it.norm),
+      tint: it.tint,
+      tex: { it.tex }.to_array().map(f16),
+      bones: { it.bones }.to_array().map(|it| it as u8),
+      weights: { it.weights }.to_array().map(unorm8)
+    }
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // n by n quads in xz, every triangle with its own copy of its vertices like an unindexed export
+  fn soup(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let vertex = |x: u32, z: u32| Vertex { pos: Vec3::new(x as f32, 0., z as f32), norm: Vec3::Y, ..Vertex::empty() };
+    let mut vertices = Vec::new();
+    for x in 0..n {
+      for z in 0..n {
+        for (x, z) in [(x, z), (x, z + 1), (x + 1, z + 1), (x, z), (x + 1, z + 1), (x + 1, z)] {
+          vertices.push(vertex(x, z));
+        }
+      }
+    }
+
+    let indices = (0..vertices.len() as u32).collect();
+    (vertices, indices)
+  }
+
+  // triangles by position, rotated to start at their smallest corner so winding still counts
+  fn tris(vertices: &[Vertex], indices: &[u32]) -> Vec<[[u32; 3]; 3]> {
+    let mut res = indices.chunks_exact(3).map(|tri| {
+      let mut tri = [0, 1, 2].map(|i| vertices[tri[i] as usize].pos.to_array().map(f32::to_bits));
+      let first = (0..3).min_by_key(|i| tri[*i]).unwrap();
+      tri.rotate_left(first);
+      tri
+    }).collect::<Vec<_>>();
+    res.sort();
+    res
+  }
+
+  fn misses(indices: &[u32]) -> usize {
+    let mut fifo = Vec::new();
+    let mut res = 0;
+    for it in indices {
+      if !fifo.contains(it) {
+        res += 1;
+        if fifo.len() == CACHE_SIZE {
+          fifo.remove(0);
+        }
+
+        fifo.push(*it);
+      }
+    }
+
+    res
+  }
+
+  // a shared grid with its triangles in a scattered order
+  fn scrambled() -> (Vec<Vertex>, Vec<u32>) {
+    let (vertices, indices) = soup(16);
+    let (vertices, indices, _) = dedup(&vertices, &indices, &[]);
+    let n = indices.len() / 3;
+    let indices = (0..n).flat_map(|i| indices[i * 131 % n * 3..][..3].to_vec()).collect();
+    (vertices, indices)
+  }
+
+  #[test]
+  fn dedup_merges_identical_vertices() {
+    let (vertices, indices) = soup(4);
+    let (merged, remapped, origin) = dedup(&vertices, &indices, &[]);
+    assert_eq!(merged.len(), 25);
+    assert_eq!(tris(&merged, &remapped), tris(&vertices, &indices));
+    for (i, it) in origin.iter().enumerate() {
+      assert_eq!(vertex_bytes(&merged[i]), vertex_bytes(&vertices[*it as usize]));
+    }
+
+    // differing extra bytes keep vertices apart
+    let extra = (0..vertices.len()).map(|i| vec![(i % 6 == 0) as u8]).collect::<Vec<_>>();
+    assert!(dedup(&vertices, &indices, &extra).0.len() > 25);
+  }
+
+  #[test]
+  fn reordering_keeps_the_triangles_and_misses_less() {
+    let (vertices, indices) = scrambled();
+    let cached = optimize_cache(&indices, vertices.len());
+    assert_eq!(tris(&vertices, &cached), tris(&vertices, &indices));
+    assert!(misses(&cached) < misses(&indices) / 2, "{} vs {}", misses(&cached), misses(&indices));
+
+    let overdrawn = optimize_overdraw(&vertices, &cached);
+    assert_eq!(tris(&vertices, &overdrawn), tris(&vertices, &indices));
+    assert!(misses(&overdrawn) < misses(&indices) / 2, "{} vs {}", misses(&overdrawn), misses(&indices));
+
+    let mut fetched = overdrawn.clone();
+    let (reordered, origin) = optimize_fetch(&vertices, &mut fetched);
+    assert_eq!(tris(&reordered, &fetched), tris(&vertices, &indices));
+    assert_eq!(misses(&fetched), misses(&overdrawn));
+    assert_eq!(origin.len(), reordered.len());
+    // vertices come in the order they're first used
+    let mut next = 0;
+    for it in &fetched {
+      assert!(*it <= next);
+      next = next.max(*it + 1);
+    }
+  }
+
+  fn f32_from_f16(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1. } else { 1. };
+    let (exp, mant) = ((half >> 10) & 0x1f, (half & 0x3ff) as f32);
+    sign * match exp {
+      0 => mant * 2f32.powi(-24),
+      31 if mant == 0. => f32::INFINITY,
+      31 => f32::NAN,
+      _ => (1. + mant / 1024.) * 2f32.powi(exp as i32 - 15)
+    }
+  }
+
+  #[test]
+  fn f16_edges_and_round_trip() {
+    assert_eq!(f16(0.), 0);
+    assert_eq!(f16(-0.), 0x8000);
+    assert_eq!(f16(1.), 0x3c00);
+    assert_eq!(f16(-2.), 0xc000);
+    assert_eq!(f16(65504.), 0x7bff);
+    assert_eq!(f16(f32::INFINITY), 0x7c00);
+    assert_eq!(f16(f32::NEG_INFINITY), 0xfc00);
+    assert_eq!(f16(1e6), 0x7c00);
+    assert_eq!(f16(f32::NAN) & 0x7e00, 0x7e00);
+    // smallest normal, then subnormals down to the smallest one, then flushed to 0
+    assert_eq!(f16(2f32.powi(-14)), 0x0400);
+    assert_eq!(f16(2f32.powi(-15)), 0x0200);
+    assert_eq!(f16(2f32.powi(-24)), 0x0001);
+    assert_eq!(f16(2f32.powi(-26)), 0);
+    assert_eq!(f16(-1e-10), 0x8000);
+
+    for it in [0.5, 0.1, -0.333, 1.75, 3.25, 100.5, 2f32.powi(-20)] {
+      let back = f32_from_f16(f16(it));
+      assert!((back - it).abs() <= it.abs() / 1024., "{} came back as {}", it, back);
+    }
+  }
+
+  // what the vertex shaders do, after the snorm attribute is unpacked
+  fn oct_decode(e: [i16; 2]) -> Vec3 {
+    let e = Vec2::new(e[0] as f32, e[1] as f32) / 32767.;
+    let mut n = Vec3::new(e.x, e.y, 1. - e.x.abs() - e.y.abs());
+    if n.z < 0. {
+      let sign = Vec2::new(if n.x >= 0. { 1. } else { -1. }, if n.y >= 0. { 1. } else { -1. });
+      let xy = (Vec2::ONE - n.yx().abs()) * sign;
+      n = Vec3::new(xy.x, xy.y, n.z);
+    }
+
+    n.normalize()
+  }
+
+  #[test]
+  fn oct_encode_matches_the_shader() {
+    let mut norms = vec![Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z, Vec3::new(1., -1., -1.).normalize()];
+    for i in 0..16 {
+      for j in 1..8 {
+        let (yaw, pitch) = (i as f32 / 16. * std::f32::consts::TAU, j as f32 / 8. * std::f32::consts::PI);
+        norms.push(Vec3::new(pitch.sin() * yaw.cos(), pitch.sin() * yaw.sin(), pitch.cos()));
+      }
+    }
+
+    for it in norms {
+      let back = oct_decode(oct_encode(it));
+      assert!(back.dot(it) > 0.99999, "{} came back as {}", it, back);
+    }
+  }
+}
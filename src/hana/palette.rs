@@ -23,4 +23,59 @@ impl Color {
   pub fn hex(highlight: u32, diffuse: u32, shade: u32) -> Color {
     Self::new(hex_to_vec3(highlight), hex_to_vec3(diffuse), hex_to_vec3(shade))
   }
+}
+
+// ramps of 4 colors, highlight to shade, matching the layout final_cel.frag indexes into
+pub struct Palette(pub Vec<Vec3>);
+
+impl Palette {
+  pub const RAMP_LEN: usize = 4;
+
+  pub fn new(colors: &[Vec3]) -> Palette {
+    Palette(colors.to_vec())
+  }
+
+  pub fn n_ramps(&self) -> usize {
+    self.0.len() / Self::RAMP_LEN
+  }
+
+  pub fn ramp(&self, idx: usize) -> &[Vec3] {
+    &self.0[idx * Self::RAMP_LEN..(idx + 1) * Self::RAMP_LEN]
+  }
+
+  // index of the ramp holding the color closest to `color`, or -1 for an empty palette
+  pub fn nearest_ramp(&self, color: Vec3) -> i32 {
+    let mut best = (-1, f32::MAX);
+    for i in 0..self.n_ramps() {
+      for it in self.ramp(i) {
+        let dist = it.distance_squared(color);
+        if dist < best.1 {
+          best = (i as i32, dist);
+        }
+      }
+    }
+
+    best.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nearest_ramp_snaps_to_the_closest_color() {
+    let palette = Palette::new(&[
+      hex_to_vec3(0xffffff), hex_to_vec3(0xff8080), hex_to_vec3(0xff0000), hex_to_vec3(0x800000),
+      hex_to_vec3(0x80ff80), hex_to_vec3(0x00ff00), hex_to_vec3(0x008000), hex_to_vec3(0x004000)
+    ]);
+
+    assert_eq!(palette.nearest_ramp(hex_to_vec3(0xff0000)), 0);
+    assert_eq!(palette.nearest_ramp(hex_to_vec3(0x900000)), 0);
+    assert_eq!(palette.nearest_ramp(hex_to_vec3(0x00f000)), 1);
+    assert_eq!(palette.nearest_ramp(hex_to_vec3(0x003000)), 1);
+    // the first ramp's highlight is white, so that wins over the green ramp's pale highlight
+    assert_eq!(palette.nearest_ramp(Vec3::ONE), 0);
+    assert_eq!(Palette::new(&[]).nearest_ramp(Vec3::ONE), -1);
+  }
 }
\ No newline at end of file
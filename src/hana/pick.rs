@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+use glam::{IVec2, Vec3};
+use crate::hana::glu::{Buf, Fbo, Fence};
+
+// g buffer attachments the picker reads, keep in sync with g_buffer_cel.frag
+pub const POS_ATTACHMENT: u32 = gl::COLOR_ATTACHMENT0;
+pub const ID_ATTACHMENT: u32 = gl::COLOR_ATTACHMENT3;
+
+// 0 in the id attachment, nothing was drawn there
+pub const NO_ID: u32 = 0;
+
+// reads older than this many frames back are still waiting on the gpu, further requests are dropped
+const IN_FLIGHT: usize = 3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pick {
+  // see World::by_id
+  pub id: u32,
+  pub pos: Vec3
+}
+
+struct Pending {
+  buf: Buf,
+  fence: Fence
+}
+
+// id + rgba position
+const PICK_BYTES: usize = size_of::<u32>() + size_of::<[f32; 4]>();
+
+// reads the id and world position under a point back from the g buffer a frame or two
+// later, so picking never stalls the pipeline
+pub struct Picker {
+  pending: VecDeque<Pending>,
+  free: Vec<Buf>
+}
+
+impl Picker {
+  pub fn new() -> Picker {
+    Picker { pending: VecDeque::new(), free: Vec::new() }
+  }
+
+  // `point` is in g buffer pixels from the bottom left, call once the g buffer pass is done.
+  // false when too many picks are still in flight
+  pub fn request(&mut self, g_buf: &Fbo, point: IVec2) -> bool {
+    if self.pending.len() >= IN_FLIGHT {
+      return false;
+    }
+
+    let buf = self.free.pop().unwrap_or_else(|| {
+      let buf = Buf::new(gl::PIXEL_PACK_BUFFER);
+      buf.storage(gl::STREAM_READ, PICK_BYTES);
+      buf
+    });
+
+    g_buf.read_pixel(ID_ATTACHMENT, point, gl::RED_INTEGER, gl::UNSIGNED_INT, &buf, 0);
+    g_buf.read_pixel(POS_ATTACHMENT, point, gl::RGBA, gl::FLOAT, &buf, size_of::<u32>());
+    self.pending.push_back(Pending { buf, fence: Fence::new() });
+    true
+  }
+
+  // the oldest request once the gpu has finished it, in the order they were made
+  pub fn poll(&mut self) -> Option<Pick> {
+    if !self.pending.front()?.fence.signaled() {
+      return None;
+    }
+
+    let Pending { buf, .. } = self.pending.pop_front()?;
+    let mut id = [0u32];
+    let mut pos = [0f32; 4];
+    buf.get_sub_data(0, &mut id);
+    buf.get_sub_data(size_of::<u32>(), &mut pos);
+    self.free.push(buf);
+    Some(Pick { id: id[0], pos: Vec3::new(pos[0], pos[1], pos[2]) })
+  }
+}
+
+impl Default for Picker {
+  fn default() -> Self {
+    Self::new()
+  }
+}
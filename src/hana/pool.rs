@@ -0,0 +1,263 @@
+use std::cell::RefCell;
+use std::mem::size_of;
+use crate::hana::glu::{Attrib, Buf, gl_copy_buffer, gl_gen_vi, gl_rebind_vi, gl_stride, Vao};
+use crate::hana::model::ATTRIBS;
+use crate::hana::opt::PACKED_ATTRIBS;
+
+// one pool per vertex layout, every mesh in it shares a single vao
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Format {
+  Full,
+  Packed
+}
+
+impl Format {
+  pub fn of(packed: bool) -> Format {
+    if packed { Format::Packed } else { Format::Full }
+  }
+
+  pub fn stride(&self) -> usize {
+    gl_stride(self.attribs()) as usize
+  }
+
+  fn attribs(&self) -> &'static [Attrib] {
+    match self {
+      Format::Full => &ATTRIBS,
+      Format::Packed => &PACKED_ATTRIBS
+    }
+  }
+}
+
+// where a mesh lives in its pool, in vertices and indices
+#[derive(Clone, Copy, Debug)]
+pub struct Slot {
+  pub base_vertex: i32,
+  pub first_index: u32,
+  n_vertices: usize,
+  n_indices: usize
+}
+
+// first fit over freed ranges, then the end
+struct Arena {
+  capacity: usize,
+  top: usize,
+  free: Vec<(usize, usize)>
+}
+
+impl Arena {
+  fn new(capacity: usize) -> Arena {
+    Arena { capacity, top: 0, free: Vec::new() }
+  }
+
+  fn alloc(&mut self, len: usize) -> Option<usize> {
+    if let Some(i) = self.free.iter().position(|it| it.1 >= len) {
+      let (start, free) = self.free[i];
+      if free == len {
+        self.free.remove(i);
+      } else {
+        self.free[i] = (start + len, free - len);
+      }
+
+      return Some(start);
+    }
+
+    if self.top + len > self.capacity {
+      return None;
+    }
+
+    self.top += len;
+    Some(self.top - len)
+  }
+
+  // keeps the free list sorted and merged
+  fn free(&mut self, start: usize, len: usize) {
+    if len == 0 {
+      return;
+    }
+
+    let i = self.free.partition_point(|it| it.0 < start);
+    self.free.insert(i, (start, len));
+    if i + 1 < self.free.len() && self.free[i].0 + self.free[i].1 == self.free[i + 1].0 {
+      self.free[i].1 += self.free.remove(i + 1).1;
+    }
+
+    if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == self.free[i].0 {
+      self.free[i - 1].1 += self.free.remove(i).1;
+    }
+
+    // give the tail back to the bump allocator
+    if let Some(last) = self.free.last().copied() {
+      if last.0 + last.1 == self.top {
+        self.top = last.0;
+        self.free.pop();
+      }
+    }
+  }
+}
+
+pub struct Pool {
+  pub format: Format,
+  pub vao: Vao,
+  vbo: Buf,
+  ibo: Buf,
+  vertices: Arena,
+  indices: Arena
+}
+
+const INITIAL_VERTICES: usize = 1 << 16;
+const INITIAL_INDICES: usize = 1 << 18;
+
+impl Pool {
+  fn new(format: Format) -> Pool {
+    let (vao, vbo, ibo) = gl_gen_vi(format.attribs());
+    vbo.storage(gl::STATIC_DRAW, INITIAL_VERTICES * format.stride());
+    ibo.storage(gl::STATIC_DRAW, INITIAL_INDICES * size_of::<u32>());
+    Pool { format, vao, vbo, ibo, vertices: Arena::new(INITIAL_VERTICES), indices: Arena::new(INITIAL_INDICES) }
+  }
+
+  // `vertices` has to match the pool's layout
+  pub fn alloc<V>(&mut self, vertices: &[V], indices: &[u32]) -> Slot {
+    assert_eq!(size_of::<V>(), self.format.stride());
+    let base_vertex = match self.vertices.alloc(vertices.len()) {
+      Some(it) => it,
+      None => {
+        self.grow(vertices.len(), 0);
+        self.vertices.alloc(vertices.len()).unwrap()
+      }
+    };
+
+    let first_index = match self.indices.alloc(indices.len()) {
+      Some(it) => it,
+      None => {
+        self.grow(0, indices.len());
+        self.indices.alloc(indices.len()).unwrap()
+      }
+    };
+
+    self.vbo.sub_data(base_vertex * self.format.stride(), vertices);
+    self.ibo.sub_data(first_index * size_of::<u32>(), indices);
+    Slot { base_vertex: base_vertex as i32, first_index: first_index as u32, n_vertices: vertices.len(), n_indices: indices.len() }
+  }
+
+  pub fn free(&mut self, slot: &Slot) {
+    self.vertices.free(slot.base_vertex as usize, slot.n_vertices);
+    self.indices.free(slot.first_index as usize, slot.n_indices);
+  }
+
+  // doubles until `vertices` / `indices` more fit at the end, slots keep their offsets.
+  // only the buffers that are too small get replaced
+  fn grow(&mut self, vertices: usize, indices: usize) {
+    let stride = self.format.stride();
+    let mut n_vertices = self.vertices.capacity;
+    while self.vertices.top + vertices > n_vertices {
+      n_vertices *= 2;
+    }
+
+    let mut n_indices = self.indices.capacity;
+    while self.indices.top + indices > n_indices {
+      n_indices *= 2;
+    }
+
+    if n_vertices != self.vertices.capacity {
+      let vbo = Buf::new(gl::ARRAY_BUFFER);
+      vbo.storage(gl::STATIC_DRAW, n_vertices * stride);
+      gl_copy_buffer(&self.vbo, &vbo, self.vertices.top * stride);
+      std::mem::replace(&mut self.vbo, vbo).delete();
+      self.vertices.capacity = n_vertices;
+    }
+
+    if n_indices != self.indices.capacity {
+      let ibo = Buf::new(gl::ELEMENT_ARRAY_BUFFER);
+      ibo.storage(gl::STATIC_DRAW, n_indices * size_of::<u32>());
+      gl_copy_buffer(&self.ibo, &ibo, self.indices.top * size_of::<u32>());
+      std::mem::replace(&mut self.ibo, ibo).delete();
+      self.indices.capacity = n_indices;
+    }
+
+    gl_rebind_vi(&self.vao, &self.vbo, &self.ibo, self.format.attribs());
+  }
+}
+
+thread_local! {
+  // made on first use, after the gl context exists
+  static POOLS: RefCell<Vec<Pool>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn with<R>(format: Format, f: impl FnOnce(&mut Pool) -> R) -> R {
+  POOLS.with(|pools| {
+    let mut pools = pools.borrow_mut();
+    let i = match pools.iter().position(|it| it.format == format) {
+      Some(i) => i,
+      None => {
+        pools.push(Pool::new(format));
+        pools.len() - 1
+      }
+    };
+
+    f(&mut pools[i])
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Arena;
+
+  #[test]
+  fn alloc_bumps_then_reuses_freed_ranges() {
+    let mut arena = Arena::new(100);
+    assert_eq!(arena.alloc(10), Some(0));
+    assert_eq!(arena.alloc(20), Some(10));
+    assert_eq!(arena.alloc(30), Some(30));
+    assert_eq!(arena.top, 60);
+
+    arena.free(10, 20);
+    assert_eq!(arena.free, [(10, 20)]);
+    // first fit, splitting the free range
+    assert_eq!(arena.alloc(5), Some(10));
+    assert_eq!(arena.free, [(15, 15)]);
+    assert_eq!(arena.alloc(15), Some(15));
+    assert!(arena.free.is_empty());
+    // too big for any free range, so from the end
+    arena.free(0, 10);
+    assert_eq!(arena.alloc(11), Some(60));
+    assert_eq!(arena.free, [(0, 10)]);
+  }
+
+  #[test]
+  fn free_merges_with_its_neighbours() {
+    let mut arena = Arena::new(100);
+    for _ in 0..5 {
+      arena.alloc(10);
+    }
+
+    arena.free(10, 10);
+    arena.free(30, 10);
+    assert_eq!(arena.free, [(10, 10), (30, 10)]);
+    arena.free(20, 10);
+    assert_eq!(arena.free, [(10, 30)]);
+    arena.free(0, 10);
+    assert_eq!(arena.free, [(0, 40)]);
+    arena.free(0, 0);
+    assert_eq!(arena.free, [(0, 40)]);
+
+    // touching the top gives everything back to the bump allocator
+    arena.free(40, 10);
+    assert!(arena.free.is_empty());
+    assert_eq!(arena.top, 0);
+  }
+
+  #[test]
+  fn alloc_fails_when_out_of_space() {
+    let mut arena = Arena::new(100);
+    assert_eq!(arena.alloc(60), Some(0));
+    assert_eq!(arena.alloc(30), Some(60));
+    assert_eq!(arena.alloc(11), None);
+    assert_eq!(arena.alloc(10), Some(90));
+    assert_eq!(arena.alloc(1), None);
+
+    // freed ranges that are too small don't help either
+    arena.free(0, 5);
+    assert_eq!(arena.alloc(6), None);
+    assert_eq!(arena.alloc(5), Some(0));
+  }
+}
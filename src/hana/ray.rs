@@ -0,0 +1,230 @@
+use glam::{Mat4, Vec3};
+use crate::hana::bounds::Aabb;
+use crate::hana::model::Vertex;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+  pub origin: Vec3,
+  // not necessarily normalised, distances along the ray are in multiples of it
+  pub dir: Vec3
+}
+
+impl Ray {
+  pub fn new(origin: Vec3, dir: Vec3) -> Ray {
+    Ray { origin, dir }
+  }
+
+  pub fn at(&self, t: f32) -> Vec3 {
+    self.origin + self.dir * t
+  }
+
+  // t stays the same on both sides, so hits in different spaces can be compared directly
+  pub fn transform(&self, mat: &Mat4) -> Ray {
+    Ray { origin: mat.transform_point3(self.origin), dir: mat.transform_vector3(self.dir) }
+  }
+
+  // slab test, the entry distance if the box is hit before `max_t`
+  pub fn intersects_aabb(&self, aabb: &Aabb, max_t: f32) -> Option<f32> {
+    let inv = self.dir.recip();
+    let a = (aabb.min - self.origin) * inv;
+    let b = (aabb.max - self.origin) * inv;
+    // nans from 0 * inf fall out of min / max in favour of the other operand
+    let near = a.min(b).max_element().max(0.);
+    let far = a.max(b).min_element().min(max_t);
+    if near <= far { Some(near) } else { None }
+  }
+
+  // möller & trumbore, hits from either side
+  pub fn intersects_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let (ab, ac) = (b - a, c - a);
+    let p = self.dir.cross(ac);
+    let det = ab.dot(p);
+    if det.abs() < 1e-12 {
+      return None;
+    }
+
+    let inv = 1. / det;
+    let s = self.origin - a;
+    let u = s.dot(p) * inv;
+    if !(0. ..=1.).contains(&u) {
+      return None;
+    }
+
+    let q = s.cross(ab);
+    let v = self.dir.dot(q) * inv;
+    if v < 0. || u + v > 1. {
+      return None;
+    }
+
+    let t = ac.dot(q) * inv;
+    if t >= 0. { Some(t) } else { None }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+  pub t: f32,
+  pub pos: Vec3,
+  // geometric, facing back along the ray
+  pub norm: Vec3,
+  // into the mesh's full detail indices, in threes
+  pub tri: usize
+}
+
+const LEAF_TRIS: usize = 4;
+
+struct BvhNode {
+  bounds: Aabb,
+  // leaves own tris[start..start + count], inner nodes have their children at start and start + 1
+  start: usize,
+  count: usize
+}
+
+// over a mesh's triangles, split at the median of the longest axis
+pub struct Bvh {
+  nodes: Vec<BvhNode>,
+  tris: Vec<u32>
+}
+
+fn tri_pos(vertices: &[Vertex], indices: &[u32], tri: u32) -> [Vec3; 3] {
+  [0, 1, 2].map(|it| vertices[indices[tri as usize * 3 + it] as usize].pos)
+}
+
+impl Bvh {
+  pub fn new(vertices: &[Vertex], indices: &[u32]) -> Bvh {
+    let mut tris = (0..(indices.len() / 3) as u32).collect::<Vec<_>>();
+    let centers = tris.iter().map(|it| {
+      let [a, b, c] = tri_pos(vertices, indices, *it);
+      (a + b + c) / 3.
+    }).collect::<Vec<_>>();
+
+    let mut nodes = vec![BvhNode { bounds: Aabb::EMPTY, start: 0, count: tris.len() }];
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+      let (start, count) = (nodes[i].start, nodes[i].count);
+      let range = start..start + count;
+      nodes[i].bounds = Aabb::from_points(tris[range.clone()].iter().flat_map(|it| tri_pos(vertices, indices, *it)));
+      if count <= LEAF_TRIS {
+        continue;
+      }
+
+      let split = Aabb::from_points(tris[range.clone()].iter().map(|it| centers[*it as usize]));
+      let size = split.max - split.min;
+      let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+      let mid = count / 2;
+      tris[range].select_nth_unstable_by(mid, |a, b| centers[*a as usize][axis].total_cmp(&centers[*b as usize][axis]));
+
+      let left = nodes.len();
+      nodes.push(BvhNode { bounds: Aabb::EMPTY, start, count: mid });
+      nodes.push(BvhNode { bounds: Aabb::EMPTY, start: start + mid, count: count - mid });
+      nodes[i] = BvhNode { bounds: nodes[i].bounds, start: left, count: 0 };
+      stack.push(left);
+      stack.push(left + 1);
+    }
+
+    Bvh { nodes, tris }
+  }
+
+  // the closest hit before `max_t`, `vertices` and `indices` have to be the ones this was built from
+  pub fn raycast(&self, ray: &Ray, vertices: &[Vertex], indices: &[u32], max_t: f32) -> Option<Hit> {
+    if self.tris.is_empty() {
+      return None;
+    }
+
+    let mut best: Option<(f32, u32)> = None;
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+      let node = &self.nodes[i];
+      let limit = best.map_or(max_t, |it| it.0);
+      if ray.intersects_aabb(&node.bounds, limit).is_none() {
+        continue;
+      }
+
+      if node.count == 0 {
+        stack.push(node.start);
+        stack.push(node.start + 1);
+        continue;
+      }
+
+      for tri in &self.tris[node.start..node.start + node.count] {
+        let [a, b, c] = tri_pos(vertices, indices, *tri);
+        if let Some(t) = ray.intersects_triangle(a, b, c) {
+          if t <= best.map_or(max_t, |it| it.0) {
+            best = Some((t, *tri));
+          }
+        }
+      }
+    }
+
+    let (t, tri) = best?;
+    let [a, b, c] = tri_pos(vertices, indices, tri);
+    let norm = (b - a).cross(c - a).normalize_or_zero();
+    Some(Hit { t, pos: ray.at(t), norm: if norm.dot(ray.dir) > 0. { -norm } else { norm }, tri: tri as usize })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use glam::{Mat4, Vec3};
+  use crate::hana::bounds::Aabb;
+  use crate::hana::model::Vertex;
+  use super::{Bvh, Ray};
+
+  // a row of unit squares facing +z, square i at x = i and z = -i
+  fn steps(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for i in 0..n {
+      let base = vertices.len() as u32;
+      for (x, y) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+        vertices.push(Vertex { pos: Vec3::new(i as f32 + x, y, -(i as f32)), norm: Vec3::Z, ..Vertex::empty() });
+      }
+
+      indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (vertices, indices)
+  }
+
+  #[test]
+  fn hits_a_triangle() {
+    let ray = Ray::new(Vec3::new(0.25, 0.25, 5.), Vec3::new(0., 0., -2.));
+    let t = ray.intersects_triangle(Vec3::ZERO, Vec3::X, Vec3::Y).unwrap();
+    assert!((t - 2.5).abs() < 1e-6);
+    assert!(ray.intersects_triangle(Vec3::new(1., 1., 0.), Vec3::new(2., 1., 0.), Vec3::new(1., 2., 0.)).is_none());
+    // pointing away
+    assert!(Ray::new(Vec3::new(0.25, 0.25, 5.), Vec3::Z).intersects_triangle(Vec3::ZERO, Vec3::X, Vec3::Y).is_none());
+  }
+
+  #[test]
+  fn slab_test() {
+    let aabb = Aabb::new(Vec3::splat(-1.), Vec3::ONE);
+    let ray = Ray::new(Vec3::new(0., 0., 5.), Vec3::NEG_Z);
+    assert_eq!(ray.intersects_aabb(&aabb, 100.), Some(4.));
+    assert_eq!(ray.intersects_aabb(&aabb, 3.), None);
+    assert_eq!(Ray::new(Vec3::ZERO, Vec3::X).intersects_aabb(&aabb, 100.), Some(0.));
+  }
+
+  #[test]
+  fn transforms_keep_t() {
+    let ray = Ray::new(Vec3::new(1., 2., 3.), Vec3::new(0., 0., -1.));
+    let moved = ray.transform(&Mat4::from_translation(Vec3::new(5., 0., 0.)));
+    assert_eq!(moved.at(2.), ray.at(2.) + Vec3::new(5., 0., 0.));
+  }
+
+  #[test]
+  fn bvh_finds_the_closest() {
+    let (vertices, indices) = steps(16);
+    let bvh = Bvh::new(&vertices, &indices);
+    // square 5's upper triangle, in front of nothing else
+    let hit = bvh.raycast(&Ray::new(Vec3::new(5.25, 0.75, 10.), Vec3::NEG_Z), &vertices, &indices, 100.).unwrap();
+    assert!((hit.t - 15.).abs() < 1e-5);
+    assert_eq!(hit.tri, 11);
+    assert_eq!(hit.norm, Vec3::Z);
+
+    // along the row, it passes through every square and square 0 is closest
+    let hit = bvh.raycast(&Ray::new(Vec3::new(-0.5, 0.5, 1.), Vec3::new(1., 0., -1.)), &vertices, &indices, 100.).unwrap();
+    assert!(hit.tri < 2);
+    assert!(bvh.raycast(&Ray::new(Vec3::new(5.25, 0.75, 10.), Vec3::NEG_Z), &vertices, &indices, 10.).is_none());
+  }
+}
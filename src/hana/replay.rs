@@ -0,0 +1,168 @@
+use std::fs;
+use crate::hana::cache::{fnv1a, FNV_BASIS};
+use crate::hana::camera::Camera;
+use crate::hana::input::Frame;
+use crate::hana::world::World;
+
+// a session's input tick by tick, with the seed the world was made from and a checksum of the
+// simulation after every tick so a replay can tell when it stops matching
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+  pub seed: u64,
+  pub ticks: Vec<(Frame, u64)>
+}
+
+// whatever the fixed tick moves. objects' transforms and clip times, and the logical camera
+pub fn checksum(world: &World, cam: &Camera) -> u64 {
+  let mut hash = FNV_BASIS;
+  let mut floats = |vals: &[f32]| {
+    for it in vals {
+      hash = fnv1a(hash, &it.to_le_bytes());
+    }
+  };
+
+  for it in &world.objs {
+    let obj = it.borrow();
+    let transform = obj.transform();
+    floats(&transform.pos.to_array());
+    floats(&transform.rot.to_array());
+    floats(&transform.scale.to_array());
+    floats(&[obj.anim().current.map_or(-1., |it| it.time)]);
+  }
+
+  floats(&cam.pos.to_array());
+  floats(&cam.velocity.to_array());
+  floats(&[cam.yaw, cam.pitch]);
+  hash
+}
+
+fn names(key: &str, names: &std::collections::BTreeSet<String>) -> Option<String> {
+  if names.is_empty() { None } else { Some(format!("{}={}", key, names.iter().cloned().collect::<Vec<_>>().join(","))) }
+}
+
+impl Recording {
+  pub fn new(seed: u64) -> Recording {
+    Recording { seed, ticks: Vec::new() }
+  }
+
+  // plain text, a `tick <checksum> [held=a,b] [pressed=..] [released=..] [axis=val]..` line per tick
+  pub fn save(&self, path: &str) -> Result<(), String> {
+    let mut res = format!("# hana replay\nseed {}\n", self.seed);
+    for (frame, checksum) in &self.ticks {
+      let mut words = vec!["tick".to_string(), format!("{:016x}", checksum)];
+      words.extend(names("held", &frame.held));
+      words.extend(names("pressed", &frame.pressed));
+      words.extend(names("released", &frame.released));
+      words.extend(frame.axes.iter().map(|(name, val)| format!("{}={}", name, val)));
+      res += &(words.join(" ") + "\n");
+    }
+
+    fs::write(path, res).map_err(|e| path.to_string() + ": " + &e.to_string())
+  }
+
+  pub fn load(path: &str) -> Result<Recording, String> {
+    let text = fs::read_to_string(path).map_err(|e| path.to_string() + ": " + &e.to_string())?;
+    let mut res = Recording::default();
+    for (n, line) in text.lines().enumerate() {
+      let err = |what: &str| format!("{}:{}: {}", path, n + 1, what);
+      let words = line.split_whitespace().collect::<Vec<_>>();
+      match words.as_slice() {
+        [] => {}
+        [it, ..] if it.starts_with('#') => {}
+        ["seed", seed] => res.seed = seed.parse().map_err(|_| err("bad seed"))?,
+        ["tick", checksum, rest @ ..] => {
+          let checksum = u64::from_str_radix(checksum, 16).map_err(|_| err("bad checksum"))?;
+          let mut frame = Frame::default();
+          for it in rest {
+            let (key, val) = it.split_once('=').ok_or_else(|| err("expected name=value"))?;
+            let list = || val.split(',').map(str::to_string);
+            match key {
+              "held" => frame.held.extend(list()),
+              "pressed" => frame.pressed.extend(list()),
+              "released" => frame.released.extend(list()),
+              _ => { frame.axes.insert(key.to_string(), val.parse().map_err(|_| err("bad axis value"))?); }
+            }
+          }
+
+          res.ticks.push((frame, checksum));
+        }
+        _ => return Err(err("expected `seed` or `tick`"))
+      }
+    }
+
+    Ok(res)
+  }
+}
+
+// feeds a recording back through the fixed tick, one frame per tick
+pub struct Replay {
+  pub recording: Recording,
+  tick: usize,
+  diverged: Option<usize>
+}
+
+impl Replay {
+  pub fn new(recording: Recording) -> Replay {
+    Replay { recording, tick: 0, diverged: None }
+  }
+
+  // input for the coming tick, None once it's all been played
+  pub fn next(&mut self) -> Option<Frame> {
+    let frame = self.recording.ticks.get(self.tick)?.0.clone();
+    self.tick += 1;
+    Some(frame)
+  }
+
+  // after the tick next was called for. false when it doesn't match the recording
+  pub fn check(&mut self, checksum: u64) -> bool {
+    let Some(tick) = self.tick.checked_sub(1) else {
+      return true;
+    };
+
+    let matches = self.recording.ticks[tick].1 == checksum;
+    if !matches && self.diverged.is_none() {
+      self.diverged = Some(tick);
+    }
+
+    matches
+  }
+
+  // the first tick that didn't match
+  pub fn diverged(&self) -> Option<usize> {
+    self.diverged
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::hana::input::Frame;
+  use super::{Recording, Replay};
+
+  #[test]
+  fn save_load_and_check() {
+    let mut frame = Frame::default();
+    frame.held.insert("sprint".into());
+    frame.pressed.extend(["orbit".to_string(), "pick".to_string()]);
+    frame.axes.insert("look_x".into(), -0.1);
+
+    let mut recording = Recording::new(42);
+    recording.ticks.push((frame, 7));
+    recording.ticks.push((Default::default(), u64::MAX));
+
+    let path = std::env::temp_dir().join("hana_replay_test.txt");
+    let path = path.to_str().unwrap();
+    recording.save(path).unwrap();
+    let loaded = Recording::load(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(loaded.seed, 42);
+    assert_eq!(loaded.ticks, recording.ticks);
+
+    let mut replay = Replay::new(loaded);
+    assert_eq!(replay.next(), Some(recording.ticks[0].0.clone()));
+    assert!(replay.check(7));
+    assert!(replay.next().is_some());
+    assert!(!replay.check(0));
+    assert_eq!(replay.next(), None);
+    assert_eq!(replay.diverged(), Some(1));
+  }
+}
@@ -0,0 +1,68 @@
+use glam::{EulerRot, Quat, Vec3};
+use noise::{NoiseFn, Perlin};
+use crate::hana::anim::TICK_LENGTH;
+
+// trauma based camera shake. gameplay adds trauma, it wears off over ticks, and the view gets
+// knocked about by trauma squared through smooth noise. only the rendered view moves, see Camera::look_at
+#[derive(Clone)]
+pub struct Shake {
+  // 0..1
+  pub trauma: f32,
+  prev_trauma: f32,
+  // trauma lost per second
+  pub decay: f32,
+  // degrees of yaw, pitch and roll at full trauma
+  pub max_angle: Vec3,
+  // world units along the camera's right, up and front at full trauma
+  pub max_offset: Vec3,
+  // how fast the noise is walked through, roughly shakes per second
+  pub frequency: f32,
+  time: f32,
+  noise: Perlin
+}
+
+impl Shake {
+  pub fn new(seed: u32) -> Shake {
+    Shake {
+      trauma: 0.,
+      prev_trauma: 0.,
+      decay: 1.,
+      max_angle: Vec3::new(4., 4., 6.),
+      max_offset: Vec3::splat(0.1),
+      frequency: 12.,
+      time: 0.,
+      noise: Perlin::new(seed)
+    }
+  }
+
+  // impulses stack up to full trauma
+  pub fn add(&mut self, trauma: f32) {
+    self.trauma = (self.trauma + trauma).clamp(0., 1.);
+  }
+
+  pub fn tick(&mut self) {
+    self.prev_trauma = self.trauma;
+    self.trauma = (self.trauma - self.decay * TICK_LENGTH).max(0.);
+    self.time += TICK_LENGTH;
+  }
+
+  // -1..1 for each of the `n`th set of three channels
+  fn sample(&self, time: f32, n: usize) -> Vec3 {
+    let t = (time * self.frequency) as f64;
+    Vec3::from_array([0, 1, 2].map(|it| self.noise.get([t, (n * 3 + it) as f64 * 7.3]) as f32))
+  }
+
+  // camera space offset and rotation to put on top of the view between ticks
+  pub fn offset(&self, tick_delta: f32) -> (Vec3, Quat) {
+    let trauma = self.prev_trauma + (self.trauma - self.prev_trauma) * tick_delta;
+    if trauma <= 0. {
+      return (Vec3::ZERO, Quat::IDENTITY);
+    }
+
+    let amount = trauma * trauma;
+    let time = self.time + (tick_delta - 1.) * TICK_LENGTH;
+    let angle = self.sample(time, 0) * self.max_angle * amount;
+    let rot = Quat::from_euler(EulerRot::YXZ, angle.x.to_radians(), angle.y.to_radians(), angle.z.to_radians());
+    (self.sample(time, 1) * self.max_offset * amount, rot)
+  }
+}
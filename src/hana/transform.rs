@@ -0,0 +1,48 @@
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+  pub pos: Vec3,
+  pub rot: Quat,
+  pub scale: Vec3,
+}
+
+impl Transform {
+  pub const IDENTITY: Transform = Transform { pos: Vec3::ZERO, rot: Quat::IDENTITY, scale: Vec3::ONE };
+
+  pub fn new(pos: Vec3, rot: Quat, scale: Vec3) -> Transform {
+    Transform { pos, rot, scale }
+  }
+
+  pub fn from_pos(pos: Vec3) -> Transform {
+    Transform { pos, ..Self::IDENTITY }
+  }
+
+  pub fn from_mat4(mat: &Mat4) -> Transform {
+    let (scale, rot, pos) = mat.to_scale_rotation_translation();
+    Transform { pos, rot, scale }
+  }
+
+  pub fn matrix(&self) -> Mat4 {
+    Mat4::from_scale_rotation_translation(self.scale, self.rot, self.pos)
+  }
+
+  pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+    Transform {
+      pos: self.pos.lerp(other.pos, t),
+      rot: self.rot.slerp(other.rot, t),
+      scale: self.scale.lerp(other.scale, t),
+    }
+  }
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self::IDENTITY
+  }
+}
+
+// inverse transpose of the upper 3x3, so non-uniform scale doesn't skew normals
+pub fn norm_mat(model: &Mat4) -> Mat3 {
+  Mat3::from_mat4(*model).inverse().transpose()
+}
@@ -1,57 +1,311 @@
 use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::rc::Rc;
 use glam::{IVec2, Vec3, Vec3Swizzles};
+use crate::hana::batch::Batches;
+use crate::hana::bounds::{Aabb, CullStats};
+use crate::hana::camera::View;
 use crate::hana::cvt::ScuffedInto;
+use crate::hana::lod;
 use crate::hana::entity::Object;
+use crate::hana::glu::Shader;
+use crate::hana::ray::{Hit, Ray};
+
+pub struct RayHit {
+  pub obj: Rc<RefCell<Object>>,
+  // into the object's Model::meshes
+  pub mesh: usize,
+  pub hit: Hit
+}
 
 pub struct World {
   pub objs: Vec<Rc<RefCell<Object>>>,
-  pub space_part: [[Vec<Rc<RefCell<Object>>>; 32]; 32]
+  pub space_part: [[Vec<Rc<RefCell<Object>>>; 32]; 32],
+  // union of the world space bounds of everything in the cell, objects can poke out of their cell
+  pub cell_bounds: [[Aabb; 32]; 32],
+  // what gets written to the g buffer's id attachment, objs[id - 1]
+  ids: HashMap<*const RefCell<Object>, u32>,
+  batches: Batches
 }
 
 fn world_to_space_part(pos: Vec3) -> IVec2 {
   (pos.xz() / 16.).floor().cvt() + IVec2::new(16, 16)
 }
 
+fn cells_around(pos: IVec2, distance: i32) -> (RangeInclusive<usize>, RangeInclusive<usize>) {
+  let min = (pos - distance).clamp(IVec2::ZERO, IVec2::splat(31));
+  let max = (pos + distance).clamp(IVec2::ZERO, IVec2::splat(31));
+  (min.x as usize..=max.x as usize, min.y as usize..=max.y as usize)
+}
+
 impl World {
-  fn do_space_part(&mut self) {
+  pub fn new() -> World {
+    World {
+      objs: Vec::new(),
+      space_part: std::array::from_fn(|_| std::array::from_fn(|_| Vec::new())),
+      cell_bounds: [[Aabb::EMPTY; 32]; 32],
+      ids: HashMap::new(),
+      batches: Batches::new()
+    }
+  }
+
+  pub fn add(&mut self, obj: Object) -> Rc<RefCell<Object>> {
+    let obj = Rc::new(RefCell::new(obj));
+    self.objs.push(obj.clone());
+    self.ids.insert(Rc::as_ptr(&obj), self.objs.len() as u32);
+    self.place(&obj);
+    obj
+  }
+
+  // for picks read back from the g buffer, 0 is nothing
+  pub fn by_id(&self, id: u32) -> Option<Rc<RefCell<Object>>> {
+    self.objs.get((id as usize).checked_sub(1)?).cloned()
+  }
+
+  fn place(&mut self, obj: &Rc<RefCell<Object>>) {
+    let pos = world_to_space_part(obj.borrow().world_pos());
+    if pos.x >= 32 || pos.y >= 32 || pos.y < 0 || pos.x < 0 {
+      panic!("pos out of range! {}", obj.borrow().world_pos());
+    }
+
+    let (x, y) = (pos.x as usize, pos.y as usize);
+    self.cell_bounds[x][y] = self.cell_bounds[x][y].union(&obj.borrow().bounds());
+    self.space_part[x][y].push(obj.clone());
+  }
+
+  // union of everything in the cell
+  fn rebound(&mut self, x: usize, y: usize) {
+    self.cell_bounds[x][y] = self.space_part[x][y].iter().fold(Aabb::EMPTY, |res, it| res.union(&it.borrow().bounds()));
+  }
+
+  // takes `obj` out of whichever cell it's in, it may have moved since it was put there
+  fn unplace(&mut self, obj: &Rc<RefCell<Object>>) {
     for i in 0..32 {
       for j in 0..32 {
-        self.space_part[i][j].clear();
+        if let Some(at) = self.space_part[i][j].iter().position(|it| Rc::ptr_eq(it, obj)) {
+          self.space_part[i][j].swap_remove(at);
+          self.rebound(i, j);
+          return;
+        }
       }
     }
+  }
 
-    for it in &self.objs {
-      let pos = world_to_space_part(*it.borrow().pos());
-      if pos.x >= 32 || pos.y >= 32 || pos.y < 0 || pos.x < 0 {
-        panic!("pos out of range! {}", it.borrow().pos());
+  // `child`'s transform becomes relative to `parent`, so it moves along with it from now on
+  pub fn attach(&mut self, child: &Rc<RefCell<Object>>, parent: &Rc<RefCell<Object>>) -> Result<(), String> {
+    let mut it = Some(parent.clone());
+    while let Some(cur) = it {
+      if Rc::ptr_eq(&cur, child) {
+        return Err("attaching would create a cycle!".into());
       }
 
-      self.space_part[pos.x as usize][pos.y as usize].push(it.clone());
+      it = cur.borrow().parent();
     }
+
+    self.unplace(child);
+    child.borrow_mut().set_parent(Some(parent));
+    self.place(child);
+    Ok(())
+  }
+
+  // `child`'s transform goes back to being in world space
+  pub fn detach(&mut self, child: &Rc<RefCell<Object>>) {
+    self.unplace(child);
+    child.borrow_mut().set_parent(None);
+    self.place(child);
+  }
+
+  pub fn children(&self, parent: &Rc<RefCell<Object>>) -> Vec<Rc<RefCell<Object>>> {
+    self.objs
+      .iter()
+      .filter(|it| it.borrow().parent().is_some_and(|p| Rc::ptr_eq(&p, parent)))
+      .cloned()
+      .collect()
   }
 
   pub fn tick(&mut self, eye: Vec3, update_distance: i32) {
-    let pos = world_to_space_part(eye);
+    let (xs, ys) = cells_around(world_to_space_part(eye), update_distance);
 
-    for i in pos.x - update_distance..pos.x + update_distance {
-      for j in pos.y - update_distance..pos.y + update_distance {
-        for it in &self.space_part[i as usize][j as usize] {
+    // only what ticked can have moved, so only those cells get sorted out again
+    let mut left = Vec::new();
+    for i in xs {
+      for j in ys.clone() {
+        for it in &self.space_part[i][j] {
           it.borrow_mut().tick()
         }
+
+        let cell = IVec2::new(i as i32, j as i32);
+        let (stayed, moved): (Vec<_>, Vec<_>) =
+          std::mem::take(&mut self.space_part[i][j])
+            .into_iter()
+            .partition(|it| world_to_space_part(it.borrow().world_pos()) == cell);
+        self.space_part[i][j] = stayed;
+        left.extend(moved);
+        self.rebound(i, j);
+      }
+    }
+
+    for it in left {
+      self.place(&it);
+    }
+  }
+
+  // the closest object along the ray before `max_t`. cells are visited front to back and
+  // the walk stops once the next cell starts past the best hit so far
+  pub fn raycast(&self, ray: &Ray, max_t: f32) -> Option<RayHit> {
+    self.raycast_skipping(ray, max_t, None)
+  }
+
+  // raycast, looking straight through `except`
+  pub fn raycast_except(&self, ray: &Ray, max_t: f32, except: &Rc<RefCell<Object>>) -> Option<RayHit> {
+    self.raycast_skipping(ray, max_t, Some(except))
+  }
+
+  fn raycast_skipping(&self, ray: &Ray, max_t: f32, except: Option<&Rc<RefCell<Object>>>) -> Option<RayHit> {
+    let mut cells = Vec::new();
+    for i in 0..32 {
+      for j in 0..32 {
+        if self.space_part[i][j].is_empty() {
+          continue;
+        }
+
+        if let Some(t) = ray.intersects_aabb(&self.cell_bounds[i][j], max_t) {
+          cells.push((t, i, j));
+        }
+      }
+    }
+
+    cells.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut best: Option<RayHit> = None;
+    for (t, i, j) in cells {
+      let max_t = best.as_ref().map_or(max_t, |it| it.hit.t);
+      if t > max_t {
+        break;
+      }
+
+      for obj in &self.space_part[i][j] {
+        if except.is_some_and(|it| Rc::ptr_eq(it, obj)) {
+          continue;
+        }
+
+        let max_t = best.as_ref().map_or(max_t, |it| it.hit.t);
+        let it = obj.borrow();
+        if ray.intersects_aabb(&it.bounds(), max_t).is_none() {
+          continue;
+        }
+
+        if let Some((mesh, hit)) = it.raycast(ray, max_t) {
+          best = Some(RayHit { obj: obj.clone(), mesh, hit });
+        }
       }
     }
+
+    best
   }
 
-  pub fn draw(&self, tick_delta: f32, eye: Vec3, render_distance: i32) {
-    let pos = world_to_space_part(eye);
+  // `shader` has to be bound, animating objects with a skeleton go through `skinned` and anything
+  // that isn't animating through `indirect` afterwards. all three need u_proj and u_look set
+  pub fn draw(&mut self, shader: &Shader, skinned: &Shader, indirect: &Shader, tick_delta: f32, view: &View, render_distance: i32) -> CullStats {
+    let mut stats = CullStats::default();
+    let mut posed = Vec::new();
+    let (xs, ys) = cells_around(world_to_space_part(view.eye), render_distance);
+
+    for i in xs {
+      for j in ys.clone() {
+        let cell = &self.space_part[i][j];
+        if cell.is_empty() {
+          continue;
+        }
+
+        if !view.frustum.intersects_aabb(&self.cell_bounds[i][j]) {
+          stats.cells_culled += 1;
+          stats.objects_culled += cell.len() as u32;
+          continue;
+        }
+
+        for cell_obj in cell {
+          let id = self.ids[&Rc::as_ptr(cell_obj)];
+          let it = cell_obj.borrow();
+          let bounds = it.bounds();
+          if !view.frustum.intersects_aabb(&bounds) {
+            stats.objects_culled += 1;
+            continue;
+          }
 
-    for i in pos.x - render_distance..pos.x + render_distance {
-      for j in pos.y - render_distance..pos.y + render_distance {
-        for it in &self.space_part[i as usize][j as usize] {
-          it.borrow().draw(tick_delta);
+          stats.objects_drawn += 1;
+          match it.instance(id) {
+            Some(instance) => self.batches.add(it.model(), lod::level(view.coverage(&bounds.sphere())), instance),
+            None if !it.model().bones.is_empty() => posed.push((id, cell_obj.clone())),
+            None => {
+              shader.set_1ui("u_id", id);
+              it.draw(shader, tick_delta, Some((view, &mut stats)));
+              shader.set_1ui("u_id", 0);
+            }
+          }
         }
       }
     }
+
+    skinned.bind();
+    for (id, it) in posed {
+      skinned.set_1ui("u_id", id);
+      it.borrow().draw(skinned, tick_delta, Some((view, &mut stats)));
+      skinned.set_1ui("u_id", 0);
+    }
+
+    indirect.bind();
+    self.batches.draw(indirect, &mut stats);
+
+    stats
+  }
+}
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use glam::{IVec2, Vec3};
+  use crate::hana::anim::Animator;
+  use crate::hana::entity::Object;
+  use crate::hana::model::Model;
+  use crate::hana::transform::Transform;
+  use super::{world_to_space_part, World};
+
+  fn add(world: &mut World, pos: Vec3) -> Rc<RefCell<Object>> {
+    world.add(Object::Any { transform: Transform::from_pos(pos), parent: None, model: Rc::new(Model::from_meshes(Vec::new())), anim: Animator::new(), tint: -1 })
+  }
+
+  // in the cell its world position is in, and no other
+  fn in_cell(world: &World, obj: &Rc<RefCell<Object>>) -> bool {
+    let cells = (0..32).flat_map(|i| (0..32).map(move |j| (i, j)))
+      .filter(|(i, j)| world.space_part[*i][*j].iter().any(|it| Rc::ptr_eq(it, obj)))
+      .map(|(i, j)| IVec2::new(i as i32, j as i32))
+      .collect::<Vec<_>>();
+    cells == [world_to_space_part(obj.borrow().world_pos())]
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn children_follow_their_parent() {
+    let mut world = World::new();
+    let parent = add(&mut world, Vec3::new(-100., 0., 0.));
+    let child = add(&mut world, Vec3::new(100., 0., 2.));
+    assert!(world.attach(&parent, &parent).is_err());
+
+    world.attach(&child, &parent).unwrap();
+    assert!(world.attach(&parent, &child).is_err());
+    assert_eq!(child.borrow().world_pos(), Vec3::new(0., 0., 2.));
+    assert!(in_cell(&world, &child));
+    assert_eq!(world.children(&parent).len(), 1);
+
+    parent.borrow_mut().transform_mut().pos = Vec3::new(40., 0., 0.);
+    world.tick(Vec3::ZERO, 8);
+    assert_eq!(child.borrow().world_pos(), Vec3::new(140., 0., 2.));
+    assert!(in_cell(&world, &parent) && in_cell(&world, &child));
+
+    world.detach(&child);
+    assert_eq!(child.borrow().world_pos(), Vec3::new(100., 0., 2.));
+    assert!(in_cell(&world, &child));
+    assert!(world.children(&parent).is_empty());
+  }
+}